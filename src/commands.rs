@@ -0,0 +1,663 @@
+use log::{error, info, warn};
+use std::{
+    collections::{HashMap, HashSet},
+    fs,
+    io::Write,
+    path::Path,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use crate::{
+    job::ScrapeJob,
+    manifest::{manifest_path, HashingScheme, Manifest, Run},
+    nodecsv::{
+        merge::{Hashing, MergeKey, Merger},
+        nodeio::{raw_path, read_dump, NodeFormat},
+        postfilter::PostFilter,
+    },
+    pushshift::{timeconvenience::TimeConvenience, PSError, PushshiftBuilder},
+    scraperclient::{
+        client::ScraperClient,
+        events::{ScrapeEvent, ScrapeObserver},
+        hashing::{fingerprint_path, NameHasher},
+        nodestructs::OutputMode,
+        stop::{ScrapeOutcome, StopCondition},
+        stratified::Allocation,
+    },
+};
+
+// Minimum time between redraws of the progress line.
+static PROGRESS_INTERVAL: Duration = Duration::from_millis(250);
+
+/// Redraws a single line on stderr with the number of nodes, how fast they arrive, and when the
+/// target should be reached at that pace.
+struct ProgressLine {
+    target: Option<usize>,
+    state: Mutex<ProgressState>,
+}
+
+#[derive(Default)]
+struct ProgressState {
+    // When the first node arrived and how many nodes there were by then
+    first: Option<(Instant, usize)>,
+    drawn: Option<Instant>,
+}
+
+impl ProgressLine {
+    fn new(target: Option<usize>) -> Self {
+        ProgressLine {
+            target,
+            state: Mutex::new(ProgressState::default()),
+        }
+    }
+
+    fn draw(&self, total: usize) {
+        let mut state = self.state.lock().unwrap_or_else(|error| error.into_inner());
+        let now = Instant::now();
+        if state
+            .drawn
+            .is_some_and(|drawn| now - drawn < PROGRESS_INTERVAL)
+        {
+            return;
+        }
+        state.drawn = Some(now);
+        let (started, initial) = *state.first.get_or_insert((now, total));
+
+        let minutes = (now - started).as_secs_f64() / 60.0;
+        let rate = if minutes > 0.0 {
+            total.saturating_sub(initial) as f64 / minutes
+        } else {
+            0.0
+        };
+        let mut line = match self.target {
+            Some(target) => format!("\r{}/{} nodes", total, target),
+            None => format!("\r{} nodes", total),
+        };
+        line.push_str(&format!(" | {:.0} per minute", rate));
+        if let Some(target) = self.target.filter(|_| rate > 0.0) {
+            let eta = (target.saturating_sub(total) as f64 / rate * 60.0) as u64;
+            line.push_str(&format!(
+                " | ETA {}h{:02}m{:02}s",
+                eta / 3600,
+                eta % 3600 / 60,
+                eta % 60
+            ));
+        }
+
+        let mut stderr = std::io::stderr();
+        let _ = write!(stderr, "{}\x1b[K", line);
+        let _ = stderr.flush();
+    }
+}
+
+impl ScrapeObserver for ProgressLine {
+    fn on_event(&self, event: &ScrapeEvent) {
+        if let ScrapeEvent::NodeAccepted { total, .. } = event {
+            self.draw(*total);
+        }
+    }
+}
+
+/// Runs a scrape job. New scrapes refuse to overwrite an existing output while resumed scrapes
+/// require one so that a typo can't resume into or clobber the wrong file. `progress` draws a
+/// progress line on stderr.
+pub async fn scrape(job: &ScrapeJob, resume: bool, progress: bool) -> Result<(), PSError> {
+    job.validate()?;
+    let started = TimeConvenience::now();
+    let path = &job.output.path;
+    match (resume, path.is_file()) {
+        (false, true) => return Err(PSError::OutputExists(path.to_string_lossy().into())),
+        (true, false) => return Err(PSError::OutputMissing(path.to_string_lossy().into())),
+        _ => {}
+    }
+    // Read up front since a missing key would otherwise only show once the scrape is over.
+    let key = job
+        .output
+        .hash_key
+        .as_ref()
+        .map(|key| fs::read(key).map_err(PSError::reading(key)))
+        .transpose()?;
+
+    // Relative times are anchored at the start of the original scrape so resumed runs keep the
+    // same window. The resolved job is written first so that it exists even if the scrape fails.
+    let job = &job.resolve(job.anchor()?);
+    job.to_file(job.resolved_path())?;
+    let (strata_template, template) = job.templates()?;
+    let subreddit_urls = template.clone().build_batched(&job.subreddits)?;
+
+    info!("Subreddits list: {:#?}", job.subreddits);
+    let mut scraper = if resume {
+        info!("Resuming scrape at {}", path.to_string_lossy());
+        let nodes = job.output.format.read(path)?;
+        ScraperClient::from_nodes(job.limits.timeout, &subreddit_urls, nodes)?
+    } else {
+        info!("Beginning new scrape.");
+        ScraperClient::new(job.limits.timeout, &subreddit_urls)?
+    };
+    scraper
+        .set_backoff(job.limits.backoff)
+        .set_filters(job.pipeline()?);
+    if job.output.mode == OutputMode::Raw {
+        let raw = raw_path(path);
+        let existing = match resume && raw.is_file() {
+            true => read_dump(&raw, &PostFilter::default())?,
+            false => HashSet::new(),
+        };
+        scraper.keep_raw(existing);
+    }
+    if let Some(cache) = &job.cache {
+        info!(
+            "Cache at {} in {} mode.",
+            cache.directory.display(),
+            cache.mode
+        );
+        scraper.set_cache(cache.clone());
+    }
+    if progress {
+        // Only node targets have a known end.
+        let target = match job.stop {
+            None => Some(job.amount),
+            Some(StopCondition::Nodes(nodes)) => Some(nodes),
+            Some(_) => None,
+        };
+        scraper.add_observer(ProgressLine::new(target));
+    }
+    if job.check_subreddits {
+        check_subreddits(&scraper, &template, &job.subreddits).await?;
+    }
+
+    let result = match (job.window.strata, job.epochs()) {
+        (Some(buckets), (Some(after), Some(before))) => {
+            info!(
+                "Sampling {} nodes from {} buckets per subreddit",
+                job.amount, buckets
+            );
+            let allocation = if job.window.proportional {
+                Allocation::Proportional
+            } else {
+                Allocation::Equal
+            };
+            scraper
+                .scrape_stratified(
+                    &strata_template,
+                    &job.subreddits,
+                    after,
+                    before,
+                    buckets,
+                    job.amount,
+                    allocation,
+                )
+                .await
+        }
+        _ => {
+            if let Some(quota) = job.quota {
+                info!("Subreddit quota: {:?}", quota);
+                scraper
+                    .set_quotas(quota, job.amount, &template, &job.subreddits)
+                    .await?;
+            }
+            let stop = job.stop.clone().unwrap_or(StopCondition::Nodes(job.amount));
+            info!("Scraping until {}", stop);
+            scraper.scrape_until(&stop).await
+        }
+    };
+
+    if progress {
+        eprintln!();
+    }
+    // Whatever was collected is written out even if the scrape ended early.
+    match &result {
+        Ok(ScrapeOutcome::ReachedTarget) => info!("Scrape reached its target."),
+        Ok(ScrapeOutcome::Exhausted) => warn!("Ran out of items before reaching the target."),
+        Ok(ScrapeOutcome::BudgetHit) => warn!("Ran out of time or requests before the target."),
+        Ok(ScrapeOutcome::Aborted) => error!("Scrape aborted after repeated failures."),
+        Err(error) => error!("Scrape failed: {}", error.report()),
+    }
+    if scraper.view_nodes().is_empty() {
+        warn!("No nodes were scraped.");
+    }
+    // scraper.scrape_individ_users()?;
+    info!("Nodes scraped: {}", scraper.length_nodes());
+    // The nodes are written before anything else so that failing to build the edges or to
+    // write a sidecar can't lose them. Edges are aggregated by name so the scraper's nodes are
+    // only hashed afterwards.
+    info!("Writing nodes.");
+    if job.output.hash {
+        info!("Hashing names for privacy.");
+        let hasher = NameHasher::new(key.as_deref());
+        let hashed = scraper
+            .view_nodes()
+            .iter()
+            .map(|node| hasher.hash_node(node.clone()))
+            .collect();
+        job.output.format.write(path, &hashed)?;
+    } else {
+        job.output.format.write(path, scraper.view_nodes())?;
+    }
+    if job.output.mode == OutputMode::Raw {
+        scraper.raw_to_ndjson(raw_path(path))?;
+    }
+
+    if job.output.edges.is_some() {
+        match scraper
+            .scrape_author_subreddits(job.endpoint, job.output.edges_min_doc_count)
+            .await
+        {
+            Ok(()) => info!("Edges scraped: {}", scraper.view_edges().len()),
+            Err(error) => error!("Failed to scrape edges: {}", error.report()),
+        }
+    }
+    if job.output.hash {
+        scraper.hash_names(key.as_deref());
+        log_failure(
+            "fingerprint",
+            NameHasher::new(key.as_deref()).write_fingerprint(path),
+        );
+    }
+    if let Some(edges) = &job.output.edges {
+        log_failure("edges", scraper.edges_to_csv(edges));
+    }
+    if job.window.strata.is_some() {
+        log_failure(
+            "strata",
+            scraper.strata_to_csv(path.with_extension("strata.csv")),
+        );
+    }
+    for (filter, rejected) in scraper.view_rejections() {
+        info!("Filter {} rejected {} items.", filter, rejected);
+    }
+    let bots = scraper.view_bots();
+    if !bots.is_empty() {
+        info!("Dropped {} accounts as bots.", bots.len());
+        log_failure("bots", scraper.bots_to_csv(path.with_extension("bots.csv")));
+    }
+
+    // Runs before the manifest existed are lost but the rest describes the files as written.
+    let mut manifest = match resume {
+        true => Manifest::read(path)?,
+        false => None,
+    }
+    .unwrap_or_else(|| Manifest::new(job.clone()));
+    manifest.version = env!("CARGO_PKG_VERSION").to_string();
+    manifest.job = job.clone();
+    manifest.runs.push(Run {
+        started,
+        finished: TimeConvenience::now(),
+        resumed: resume,
+        outcome: match &result {
+            Ok(outcome) => format!("{:?}", outcome),
+            Err(error) => error.report(),
+        },
+        requests: scraper.requests(),
+        rejections: scraper
+            .view_rejections()
+            .into_iter()
+            .map(|(filter, rejected)| (filter.to_string(), rejected))
+            .collect(),
+    });
+    manifest.record_nodes(scraper.view_nodes());
+    manifest.hashing = job.output.hash.then(|| {
+        let hasher = NameHasher::new(key.as_deref());
+        HashingScheme {
+            algorithm: hasher.algorithm().to_string(),
+            fingerprint: hasher.fingerprint(),
+        }
+    });
+    let mut files = vec![
+        path.clone(),
+        job.resolved_path(),
+        path.with_extension("strata.csv"),
+        path.with_extension("bots.csv"),
+        raw_path(path),
+    ];
+    if job.output.hash {
+        files.push(fingerprint_path(path));
+    }
+    files.extend(job.output.edges.clone());
+    manifest.record_files(path, &files)?;
+    manifest.write(path)?;
+    result.map(|_| ())
+}
+
+// Sidecars are written after the nodes so failing to write one is only logged.
+fn log_failure(sidecar: &str, result: Result<(), PSError>) {
+    if let Err(error) = result {
+        error!("Failed to write the {}: {}", sidecar, error.report());
+    }
+}
+
+/// Checks the files of a dataset against the manifest written next to its nodes.
+pub fn verify<P: AsRef<Path>>(nodes: P) -> Result<(), PSError> {
+    let manifest = Manifest::read(&nodes)?
+        .ok_or_else(|| PSError::NoManifest(manifest_path(&nodes).to_string_lossy().into_owned()))?;
+    let problems = manifest.verify(&nodes)?;
+    if !problems.is_empty() {
+        return Err(PSError::ManifestMismatch(problems));
+    }
+    println!(
+        "{} files match the manifest written by version {} after {} runs.",
+        manifest.files.len(),
+        manifest.version,
+        manifest.runs.len()
+    );
+    Ok(())
+}
+
+/// Hashes the names in a node file that was scraped without hashing.
+pub fn hash<P, Q>(input: P, output: Q, key: Option<&[u8]>) -> Result<(), PSError>
+where
+    P: AsRef<Path>,
+    Q: AsRef<Path>,
+{
+    let hasher = NameHasher::new(key);
+    let nodes = NodeFormat::from_path(&input)
+        .read(&input)?
+        .into_iter()
+        .map(|node| hasher.hash_node(node))
+        .collect();
+    NodeFormat::from_path(&output).write(&output, &nodes)?;
+    hasher.write_fingerprint(output)
+}
+
+/// Converts a node file to the format implied by the extension of `output`.
+pub fn export<P, Q>(input: P, output: Q) -> Result<(), PSError>
+where
+    P: AsRef<Path>,
+    Q: AsRef<Path>,
+{
+    let nodes = NodeFormat::from_path(&input).read(&input)?;
+    info!("Exporting {} nodes.", nodes.len());
+    NodeFormat::from_path(&output).write(output, &nodes)
+}
+
+/// Prints the number of nodes, authors, and nodes per subreddit as well as the time span of a
+/// node file.
+pub fn stats<P>(input: P) -> Result<(), PSError>
+where
+    P: AsRef<Path>,
+{
+    let nodes = NodeFormat::from_path(&input).read(&input)?;
+    let authors: HashSet<_> = nodes.iter().map(|node| &node.author).collect();
+    let mut subreddits: HashMap<String, usize> = HashMap::new();
+    for node in nodes.iter() {
+        *subreddits.entry(node.subreddit.to_lowercase()).or_insert(0) += 1;
+    }
+    let mut subreddits: Vec<_> = subreddits.into_iter().collect();
+    subreddits.sort_by(|(sub, count), (other_sub, other_count)| {
+        other_count.cmp(count).then_with(|| sub.cmp(other_sub))
+    });
+
+    println!("Nodes: {}", nodes.len());
+    println!("Authors: {}", authors.len());
+    if let (Some(oldest), Some(newest)) = (
+        nodes.iter().map(|node| node.created_utc).min(),
+        nodes.iter().map(|node| node.created_utc).max(),
+    ) {
+        println!("Created: {} to {}", oldest, newest);
+    }
+    println!("Subreddits: {}", subreddits.len());
+    for (subreddit, count) in subreddits {
+        println!("  r/{}: {}", subreddit, count);
+    }
+    Ok(())
+}
+
+/// Combines node files into `output`, dropping nodes with the same key, and prints what each
+/// input contributed.
+pub fn merge<P, Q>(inputs: &[P], output: Q, key: MergeKey) -> Result<(), PSError>
+where
+    P: AsRef<Path>,
+    Q: AsRef<Path>,
+{
+    let mut merger = Merger::new(key);
+    for input in inputs {
+        merger.add_file(input)?;
+    }
+
+    for contribution in merger.contributions() {
+        println!(
+            "{}: {} read, {} added, {} duplicates, {} conflicts",
+            contribution.source,
+            contribution.read,
+            contribution.added,
+            contribution.duplicates,
+            contribution.conflicts
+        );
+    }
+    let fingerprint = match merger.hashing() {
+        Some(Hashing::Hashed(fingerprint)) => fingerprint.clone(),
+        _ => None,
+    };
+    let nodes = merger.into_nodes();
+    println!("Merged: {}", nodes.len());
+
+    NodeFormat::from_path(&output).write(&output, &nodes)?;
+    if let Some(fingerprint) = fingerprint {
+        let path = fingerprint_path(&output);
+        fs::write(&path, fingerprint).map_err(PSError::writing(&path))?;
+    }
+    Ok(())
+}
+
+/// Asks the API how many items each subreddit has and fails with every subreddit that is empty
+/// or couldn't be checked.
+async fn check_subreddits(
+    scraper: &ScraperClient,
+    template: &PushshiftBuilder,
+    subs: &[String],
+) -> Result<(), PSError> {
+    info!("Checking that every subreddit has items.");
+    let problems: Vec<_> = scraper
+        .populations(template, subs)
+        .await?
+        .into_iter()
+        .filter_map(|(sub, population)| match population {
+            Some(0) => Some(format!(
+                "r/{} has no items. It may be misspelled, private, or banned.",
+                sub
+            )),
+            Some(_) => None,
+            None => Some(format!("r/{} couldn't be checked.", sub)),
+        })
+        .collect();
+
+    if problems.is_empty() {
+        Ok(())
+    } else {
+        Err(PSError::SubredditChecks(problems))
+    }
+}
+
+/// Checks a job file and prints the resolved job along with the queries it would run.
+/// `check` also checks that every subreddit has items.
+pub async fn validate<P>(path: P, check: bool) -> Result<(), PSError>
+where
+    P: AsRef<Path>,
+{
+    let job = ScrapeJob::from_file(path)?;
+    let job = job.resolve(job.anchor()?);
+    let (_, template) = job.templates()?;
+
+    println!("{}", toml::to_string_pretty(&job)?);
+    if job.window.strata.is_some() {
+        println!("Stratified samples query each bucket's window separately.");
+    }
+    for url in template.clone().build_batched(&job.subreddits)? {
+        println!("{}", url);
+    }
+
+    if check {
+        let scraper = ScraperClient::new(job.limits.timeout, &[])?;
+        check_subreddits(&scraper, &template, &job.subreddits).await?;
+        println!("Every subreddit has items.");
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        nodecsv::nodeio::{read_nodes, write_nodes, write_nodes_ndjson},
+        scraperclient::{
+            cache::{CacheMode, ResponseCache},
+            hashing::read_fingerprint,
+            nodestructs::Node,
+        },
+        testdir::TestDir,
+    };
+    use reqwest::Url;
+
+    fn raw(author: &str, created_utc: u64) -> serde_json::Value {
+        serde_json::json!({
+            "author": author,
+            "created_utc": created_utc,
+            "permalink": format!("/r/gaming/comments/a/t/{}/", author),
+            "subreddit": "gaming",
+        })
+    }
+
+    fn nodes(items: &[(&str, u64)]) -> HashSet<Node> {
+        items
+            .iter()
+            .map(|(author, created_utc)| serde_json::from_value(raw(author, *created_utc)).unwrap())
+            .collect()
+    }
+
+    // Records a page of `items` as the response to `url` with its cursor moved to `before`.
+    fn record(cache: &ResponseCache, url: &Url, before: u64, items: serde_json::Value) {
+        let mut url = url.clone();
+        let pairs: Vec<_> = url
+            .query_pairs()
+            .into_owned()
+            .filter(|(param, _)| param != "before")
+            .chain([("before".to_string(), before.to_string())])
+            .collect();
+        url.query_pairs_mut().clear().extend_pairs(pairs);
+        let page = serde_json::json!({ "data": items });
+        cache.put(&url, page.to_string().as_bytes()).unwrap();
+    }
+
+    #[tokio::test]
+    async fn resumes_hashed_scrapes() {
+        let directory = TestDir::new("resume");
+        let mut job: ScrapeJob = toml::from_str(
+            "subreddits = [\"gaming\"]\namount = 2\n[output]\npath = \"nodes.csv\"\n[limits]\nbackoff = 0",
+        )
+        .unwrap();
+        job.output.path = directory.join("nodes.csv");
+        let (_, mut template) = job.templates().unwrap();
+        let url = template.build_batched(&job.subreddits).unwrap().remove(0);
+        let cache = ResponseCache::new(directory.join("cache"), CacheMode::Record);
+        record(
+            &cache,
+            &url,
+            u32::MAX.into(),
+            serde_json::json!([raw("link", 300), raw("zelda", 200)]),
+        );
+        // Resumed queries continue from the oldest loaded node.
+        record(
+            &cache,
+            &url,
+            200,
+            serde_json::json!([raw("zelda", 200), raw("samus", 100)]),
+        );
+        job.cache = Some(ResponseCache::new(
+            directory.join("cache"),
+            CacheMode::Replay,
+        ));
+
+        assert!(matches!(
+            scrape(&job, true, false).await,
+            Err(PSError::OutputMissing(_))
+        ));
+        scrape(&job, false, false).await.unwrap();
+        assert!(matches!(
+            scrape(&job, false, false).await,
+            Err(PSError::OutputExists(_))
+        ));
+        let scraped = fs::read_to_string(&job.output.path).unwrap();
+
+        job.amount = 3;
+        scrape(&job, true, false).await.unwrap();
+        let resumed = fs::read_to_string(&job.output.path).unwrap();
+        let nodes = read_nodes(&job.output.path).unwrap();
+        assert_eq!(nodes.len(), 3);
+        assert!(nodes.iter().all(Node::is_hashed));
+        for row in scraped.lines() {
+            assert!(resumed.lines().any(|line| line == row), "{}", row);
+        }
+        verify(&job.output.path).unwrap();
+    }
+
+    #[test]
+    fn hashes_files_once() {
+        let directory = TestDir::new("hash");
+        let plain = directory.join("plain.csv");
+        let hashed = directory.join("hashed.ndjson");
+        let again = directory.join("again.csv");
+        write_nodes(&plain, &nodes(&[("link", 300), ("zelda", 200)])).unwrap();
+
+        hash(&plain, &hashed, Some(b"key")).unwrap();
+        let hashed_nodes = NodeFormat::Ndjson.read(&hashed).unwrap();
+        assert_eq!(hashed_nodes.len(), 2);
+        assert!(hashed_nodes.iter().all(Node::is_hashed));
+        assert_eq!(
+            read_fingerprint(&hashed).unwrap(),
+            Some(NameHasher::new(Some(b"key")).fingerprint())
+        );
+
+        // Hashing a hashed file leaves the names as they are.
+        hash(&hashed, &again, Some(b"key")).unwrap();
+        let again_nodes = read_nodes(&again).unwrap();
+        assert_eq!(again_nodes, hashed_nodes);
+        assert_eq!(
+            again_nodes
+                .iter()
+                .map(|node| &node.permalink)
+                .collect::<HashSet<_>>(),
+            hashed_nodes
+                .iter()
+                .map(|node| &node.permalink)
+                .collect::<HashSet<_>>()
+        );
+    }
+
+    #[test]
+    fn exports_between_formats() {
+        let directory = TestDir::new("export");
+        let csv = directory.join("nodes.csv");
+        let ndjson = directory.join("nodes.ndjson");
+        let back = directory.join("back.csv");
+        write_nodes(&csv, &nodes(&[("link", 300), ("zelda", 200)])).unwrap();
+
+        export(&csv, &ndjson).unwrap();
+        assert_eq!(fs::read_to_string(&ndjson).unwrap().lines().count(), 2);
+        export(&ndjson, &back).unwrap();
+        assert_eq!(fs::read(&back).unwrap(), fs::read(&csv).unwrap());
+    }
+
+    #[test]
+    fn merges_files() {
+        let directory = TestDir::new("merge");
+        let first = directory.join("first.csv");
+        let second = directory.join("second.ndjson");
+        let hashed = directory.join("hashed.csv");
+        let merged = directory.join("merged.csv");
+        write_nodes(&first, &nodes(&[("link", 300), ("zelda", 200)])).unwrap();
+        write_nodes_ndjson(&second, &nodes(&[("zelda", 200), ("samus", 100)])).unwrap();
+
+        merge(&[&first, &second], &merged, MergeKey::Id).unwrap();
+        assert_eq!(
+            read_nodes(&merged).unwrap(),
+            nodes(&[("link", 300), ("zelda", 200), ("samus", 100)])
+        );
+
+        hash(&first, &hashed, None).unwrap();
+        assert!(matches!(
+            merge(&[&first, &hashed], &merged, MergeKey::Id),
+            Err(PSError::MixedHashing(_))
+        ));
+    }
+}
@@ -0,0 +1,620 @@
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashSet,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    nodecsv::nodeio::NodeFormat,
+    pushshift::{
+        normalize_subreddit, sortopts::Sort, timeconvenience::TimeConvenience, Comparison,
+        PSEndpoint, PSError, PushshiftBuilder, MAX_PS_FETCH_SIZE,
+    },
+    scraperclient::{
+        bots::BotFilter,
+        cache::ResponseCache,
+        client::DEFAULT_BACKOFF,
+        nodefilter::{
+            BodyLengthFilter, FilterPipeline, KeywordFilter, NsfwFilter, RemovedFilter,
+            ScoreFilter, TimeFilter,
+        },
+        nodestructs::OutputMode,
+        quota::Quota,
+        stop::StopCondition,
+    },
+};
+
+// Default number of items to scrape
+pub static DEFAULT_SCRAPE: usize = 125000;
+pub static DEFAULT_TIMEOUT: u64 = 90;
+
+/// A complete description of a scrape. Job files and command line arguments both resolve to a
+/// ScrapeJob.
+///
+/// Job files are TOML unless their extension is `.json`. Times, comparisons, and stop conditions
+/// are strings in the same formats the command line takes:
+///
+/// ```toml
+/// subreddits = ["gaming", "games"]
+/// amount = 50000
+/// stop = "authors=5000|elapsed=3600"
+///
+/// [output]
+/// path = "gaming.csv"
+/// hash_key = "secret.key"
+/// edges = "gaming.edges.csv"
+/// edges_min_doc_count = 2
+///
+/// [window]
+/// after = "2019-01-01"
+/// before = "30d"
+///
+/// [filters]
+/// score = ">1"
+///
+/// [records]
+/// removed = true
+/// keyword = "zelda|mario"
+///
+/// [bots]
+/// blocklists = ["bots.txt"]
+/// patterns = ["bot$"]
+/// max_repeats = 5
+///
+/// [limits]
+/// backoff = 5
+///
+/// [cache]
+/// directory = "responses"
+/// mode = "record"
+/// ```
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ScrapeJob {
+    /// Subreddits to scrape.
+    pub subreddits: Vec<String>,
+    #[serde(default = "default_endpoint")]
+    pub endpoint: PSEndpoint,
+    /// Amount of nodes to scrape.
+    #[serde(default = "default_amount")]
+    pub amount: usize,
+    /// Checks that every subreddit has items before scraping.
+    #[serde(default)]
+    pub check_subreddits: bool,
+    /// Stops once this condition is met instead of after amount nodes.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stop: Option<StopCondition>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub quota: Option<Quota>,
+    pub output: Output,
+    #[serde(default)]
+    pub window: Window,
+    #[serde(default)]
+    pub filters: Filters,
+    #[serde(default)]
+    pub records: Records,
+    #[serde(default)]
+    pub bots: Bots,
+    #[serde(default)]
+    pub limits: Limits,
+    /// Records responses or replays recorded ones.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cache: Option<ResponseCache>,
+}
+
+/// Where and how results are written.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Output {
+    /// Path to resume a scrape from or where to save a new scrape.
+    pub path: PathBuf,
+    /// Snowballs each author's subreddit counts into a weighted edge list at this path.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub edges: Option<PathBuf>,
+    /// Leaves out edges to subreddits where an author has fewer items than this.
+    #[serde(default = "default_min_doc_count")]
+    pub edges_min_doc_count: u32,
+    #[serde(default)]
+    pub format: NodeFormat,
+    /// Raw also writes every field of each node next to the output with a `.raw.ndjson`
+    /// extension. Requires hash = false.
+    #[serde(default)]
+    pub mode: OutputMode,
+    /// Hashes names before writing. Unhashed scrapes can be hashed later with the hash command.
+    #[serde(default = "default_hash")]
+    pub hash: bool,
+    /// File whose contents key the hashes of names. Names are hashed without a key otherwise.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub hash_key: Option<PathBuf>,
+}
+
+/// Time bounds, pagination, and stratification.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Window {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub after: Option<TimeConvenience>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub before: Option<TimeConvenience>,
+    /// Epoch that relative times count back from. Set by ScrapeJob::resolve().
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub anchor: Option<u64>,
+    #[serde(default)]
+    pub ascending: bool,
+    /// Splits [after, before) into this many time buckets per subreddit.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub strata: Option<u32>,
+    /// Allocates nodes to buckets in proportion to their sizes instead of equally.
+    #[serde(default)]
+    pub proportional: bool,
+}
+
+/// Query filters passed on to PushshiftBuilder.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Filters {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub query: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub authors: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub score: Option<Comparison>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub num_comments: Option<Comparison>,
+}
+
+/// Checks applied to each item as it arrives, after the bot filter. Unlike filters, these run
+/// locally so they also work for fields the API can't filter by.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Records {
+    /// Keeps items with this score.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub score: Option<Comparison>,
+    /// Keeps items created at or after this time.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub after: Option<TimeConvenience>,
+    /// Keeps items created before this time.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub before: Option<TimeConvenience>,
+    /// Keeps comments whose body has this many characters.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub body_length: Option<Comparison>,
+    /// Drops items whose body was removed or deleted.
+    #[serde(default)]
+    pub removed: bool,
+    /// Drops submissions marked NSFW.
+    #[serde(default)]
+    pub nsfw: bool,
+    /// Keeps items whose body or title matches this case insensitive regular expression.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub keyword: Option<String>,
+}
+
+/// Accounts dropped as bots in addition to deleted accounts and AutoModerator. Dropped accounts
+/// are listed next to the output with a `.bots.csv` extension.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Bots {
+    /// Files with one account per line. Lines starting with # are ignored.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub blocklists: Vec<PathBuf>,
+    /// Case insensitive regular expressions matched against names such as "bot$".
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub patterns: Vec<String>,
+    /// Drops accounts that post more than this many items per hour.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_rate: Option<f64>,
+    /// Drops accounts that post the same body this many times.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_repeats: Option<u32>,
+    /// Drops accounts with items distinguished by moderators or admins.
+    #[serde(default)]
+    pub distinguished: bool,
+}
+
+/// Request sizes and rate limits.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Limits {
+    /// Items requested per page.
+    #[serde(default = "default_size")]
+    pub size: u32,
+    /// Seconds to wait for each request.
+    #[serde(default = "default_timeout")]
+    pub timeout: u64,
+    /// Seconds to sleep between requests.
+    #[serde(default = "default_backoff")]
+    pub backoff: u64,
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Limits {
+            size: MAX_PS_FETCH_SIZE,
+            timeout: DEFAULT_TIMEOUT,
+            backoff: DEFAULT_BACKOFF,
+        }
+    }
+}
+
+fn default_hash() -> bool {
+    true
+}
+
+fn default_min_doc_count() -> u32 {
+    1
+}
+
+fn default_endpoint() -> PSEndpoint {
+    PSEndpoint::Comment
+}
+
+fn default_amount() -> usize {
+    DEFAULT_SCRAPE
+}
+
+fn default_size() -> u32 {
+    MAX_PS_FETCH_SIZE
+}
+
+fn default_timeout() -> u64 {
+    DEFAULT_TIMEOUT
+}
+
+fn default_backoff() -> u64 {
+    DEFAULT_BACKOFF
+}
+
+fn is_json<P: AsRef<Path>>(path: P) -> bool {
+    path.as_ref()
+        .extension()
+        .is_some_and(|extension| extension.eq_ignore_ascii_case("json"))
+}
+
+impl ScrapeJob {
+    /// Reads and validates a job file.
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Self, PSError> {
+        let contents = fs::read_to_string(&path).map_err(PSError::reading(&path))?;
+        let job: ScrapeJob = if is_json(&path) {
+            serde_json::from_str(&contents).map_err(PSError::reading(&path))?
+        } else {
+            toml::from_str(&contents).map_err(PSError::reading(&path))?
+        };
+        job.validate()?;
+        Ok(job)
+    }
+
+    /// Writes the job as TOML or as JSON if the extension is `.json`.
+    pub fn to_file<P: AsRef<Path>>(&self, path: P) -> Result<(), PSError> {
+        let contents = if is_json(&path) {
+            serde_json::to_string_pretty(self)?
+        } else {
+            toml::to_string_pretty(self)?
+        };
+        fs::write(&path, contents).map_err(PSError::writing(&path))
+    }
+
+    /// Checks the combinations of options that the command line rejects.
+    pub fn validate(&self) -> Result<(), PSError> {
+        if self.subreddits.is_empty() {
+            return Err(PSError::NoArguments);
+        }
+        let problems = self.subreddit_problems();
+        if !problems.is_empty() {
+            return Err(PSError::SubredditChecks(problems));
+        }
+
+        for pattern in self.bots.patterns.iter() {
+            BotFilter::new().pattern(pattern)?;
+        }
+        if let Some(keyword) = &self.records.keyword {
+            KeywordFilter::new(keyword)?;
+        }
+        // Edges aggregate the endpoint's items by subreddit which subreddits can't be.
+        if self.output.edges.is_some() && self.endpoint == PSEndpoint::Subreddit {
+            return Err(PSError::ConflictingParams(
+                "edges".into(),
+                "endpoint=subreddit".into(),
+            ));
+        }
+        // Bodies and titles would reveal what hashing hides.
+        if self.output.mode == OutputMode::Raw && self.output.hash {
+            return Err(PSError::ConflictingParams("raw".into(), "hash".into()));
+        }
+
+        // Relative times are compared as of the job's anchor, or now if it has none yet.
+        let anchor = self.window.anchor.unwrap_or_else(TimeConvenience::now);
+        for (after, before) in [
+            (self.window.after, self.window.before),
+            (self.records.after, self.records.before),
+        ] {
+            if let (Some(after), Some(before)) = (after, before) {
+                let (after, before) = (after.to_epoch(anchor), before.to_epoch(anchor));
+                if after >= before {
+                    return Err(PSError::InvalidTimeRange(after, before));
+                }
+            }
+        }
+
+        if self.window.strata.is_some() {
+            if self.window.after.is_none() || self.window.before.is_none() {
+                return Err(PSError::RequiresParam(
+                    "strata".into(),
+                    "after and before".into(),
+                ));
+            }
+            if self.quota.is_some() {
+                return Err(PSError::ConflictingParams("quota".into(), "strata".into()));
+            }
+            if self.stop.is_some() {
+                return Err(PSError::ConflictingParams("stop".into(), "strata".into()));
+            }
+        } else if self.window.proportional {
+            return Err(PSError::RequiresParam(
+                "proportional".into(),
+                "strata".into(),
+            ));
+        }
+
+        Ok(())
+    }
+
+    /// Every invalid or repeated subreddit name so that they can be fixed at once.
+    pub fn subreddit_problems(&self) -> Vec<String> {
+        let mut seen = HashSet::new();
+        self.subreddits
+            .iter()
+            .filter_map(|sub| match normalize_subreddit(sub) {
+                Ok(normalized) if !seen.insert(normalized.clone()) => {
+                    Some(format!("r/{} is listed more than once", normalized))
+                }
+                Ok(_) => None,
+                Err(error) => Some(error.to_string()),
+            })
+            .collect()
+    }
+
+    /// Where the resolved job is written alongside the output.
+    pub fn resolved_path(&self) -> PathBuf {
+        self.output.path.with_extension("job.toml")
+    }
+
+    /// Epoch that relative times are anchored at. Resumed scrapes reuse the anchor of the
+    /// original scrape's resolved job so that they keep the same window.
+    pub fn anchor(&self) -> Result<u64, PSError> {
+        if let Some(anchor) = self.window.anchor {
+            return Ok(anchor);
+        }
+
+        let resolved = self.resolved_path();
+        if self.output.path.is_file() && resolved.is_file() {
+            if let Some(anchor) = ScrapeJob::from_file(resolved)?.window.anchor {
+                return Ok(anchor);
+            }
+        }
+
+        Ok(TimeConvenience::now())
+    }
+
+    /// Epochs of the window's bounds. Relative times are only converted once the job is
+    /// resolved.
+    pub fn epochs(&self) -> (Option<u64>, Option<u64>) {
+        (
+            self.window.after.and_then(|after| after.epoch()),
+            self.window.before.and_then(|before| before.epoch()),
+        )
+    }
+
+    /// Filters described by the job. Reads the bot blocklists.
+    pub fn pipeline(&self) -> Result<FilterPipeline, PSError> {
+        let mut pipeline = FilterPipeline::new(self.bot_filter()?);
+        if let Some(score) = self.records.score {
+            pipeline.push(ScoreFilter(score));
+        }
+        if self.records.after.is_some() || self.records.before.is_some() {
+            let anchor = self.anchor()?;
+            pipeline.push(TimeFilter {
+                after: self.records.after.map(|after| after.to_epoch(anchor)),
+                before: self.records.before.map(|before| before.to_epoch(anchor)),
+            });
+        }
+        if let Some(length) = self.records.body_length {
+            pipeline.push(BodyLengthFilter(length));
+        }
+        if self.records.removed {
+            pipeline.push(RemovedFilter);
+        }
+        if self.records.nsfw {
+            pipeline.push(NsfwFilter);
+        }
+        if let Some(keyword) = &self.records.keyword {
+            pipeline.push(KeywordFilter::new(keyword)?);
+        }
+        Ok(pipeline)
+    }
+
+    fn bot_filter(&self) -> Result<BotFilter, PSError> {
+        let mut filter = BotFilter::new();
+        for path in self.bots.blocklists.iter() {
+            filter.read_blocklist(path)?;
+        }
+        for pattern in self.bots.patterns.iter() {
+            filter.pattern(pattern)?;
+        }
+        if let Some(rate) = self.bots.max_rate {
+            filter.max_rate(rate);
+        }
+        if let Some(repeats) = self.bots.max_repeats {
+            filter.max_repeats(repeats);
+        }
+        filter.distinguished(self.bots.distinguished);
+        Ok(filter)
+    }
+
+    /// Query templates for the job's subreddits. The first one lacks the time window since
+    /// stratified samples set their own windows.
+    pub fn templates(&self) -> Result<(PushshiftBuilder, PushshiftBuilder), PSError> {
+        // Filters may need fields that nodes don't keep.
+        let mut fields = self.output.mode.fields().to_vec();
+        fields.extend(self.pipeline()?.fields());
+        fields.sort_unstable();
+        fields.dedup();
+        let mut builder = PushshiftBuilder::new(self.endpoint);
+        builder
+            .size(self.limits.size)?
+            .fields(&fields)?
+            .metadata(true)?;
+        if let Some(query) = &self.filters.query {
+            builder.q(query)?;
+        }
+        if !self.filters.authors.is_empty() {
+            builder.author(&self.filters.authors)?;
+        }
+        if let Some(score) = self.filters.score {
+            builder.score(score)?;
+        }
+        if let Some(num_comments) = self.filters.num_comments {
+            builder.num_comments(num_comments)?;
+        }
+        if self.window.ascending {
+            builder.order(Sort::Asc)?;
+        }
+
+        let strata_template = builder.clone();
+        let (after, before) = self.epochs();
+        if self.window.strata.is_none() && (after.is_some() || before.is_some()) {
+            builder.window(after.unwrap_or(0), before.unwrap_or(u32::MAX.into()))?;
+        }
+        Ok((strata_template, builder))
+    }
+
+    /// Copy of the job with relative times converted to epochs counted back from `anchor`.
+    /// Running the resolved job again scrapes the same window.
+    pub fn resolve(&self, anchor: u64) -> ScrapeJob {
+        let mut resolved = self.clone();
+        resolved.subreddits = self
+            .subreddits
+            .iter()
+            .map(|sub| normalize_subreddit(sub).unwrap_or_else(|_| sub.clone()))
+            .collect();
+        let to_epoch = |time: TimeConvenience| TimeConvenience::UTC(time.to_epoch(anchor));
+        resolved.window.after = self.window.after.map(to_epoch);
+        resolved.window.before = self.window.before.map(to_epoch);
+        resolved.window.anchor = Some(anchor);
+        resolved.records.after = self.records.after.map(to_epoch);
+        resolved.records.before = self.records.before.map(to_epoch);
+        resolved
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    static JOB: &str = r#"
+subreddits = ["gaming", "Games"]
+amount = 500
+stop = "authors=50|elapsed=60"
+quota = { minimum = 100 }
+
+[output]
+path = "gaming.csv"
+format = "ndjson"
+
+[window]
+after = "2019-01-01"
+before = "1d"
+
+[filters]
+score = ">1"
+"#;
+
+    #[test]
+    fn resolve_round_trips() {
+        let job: ScrapeJob = toml::from_str(JOB).unwrap();
+        job.validate().unwrap();
+        assert_eq!(job.endpoint, PSEndpoint::Comment);
+        assert_eq!(job.quota, Some(Quota::Minimum(100)));
+        assert_eq!(job.output.format, NodeFormat::Ndjson);
+        assert_eq!(job.limits, Limits::default());
+
+        let resolved = job.resolve(1546473600);
+        resolved.validate().unwrap();
+        assert_eq!(
+            resolved.window.after,
+            Some(TimeConvenience::UTC(1546300800))
+        );
+        assert_eq!(
+            resolved.window.before,
+            Some(TimeConvenience::UTC(1546387200))
+        );
+
+        let toml = toml::to_string_pretty(&resolved).unwrap();
+        assert_eq!(toml::from_str::<ScrapeJob>(&toml).unwrap(), resolved);
+        let json = serde_json::to_string(&resolved).unwrap();
+        assert_eq!(serde_json::from_str::<ScrapeJob>(&json).unwrap(), resolved);
+    }
+
+    #[test]
+    fn validate_strata() {
+        let mut job: ScrapeJob = toml::from_str(JOB).unwrap();
+        job.window.strata = Some(4);
+        assert!(matches!(
+            job.validate(),
+            Err(PSError::ConflictingParams(..))
+        ));
+        job.window.after = None;
+        assert!(matches!(job.validate(), Err(PSError::RequiresParam(..))));
+    }
+
+    #[test]
+    fn validate_windows() {
+        let mut job: ScrapeJob = toml::from_str(JOB).unwrap();
+        // Anchored a day after the start of the window so that it's empty.
+        job.window.anchor = Some(1546387200);
+        assert!(matches!(
+            job.validate(),
+            Err(PSError::InvalidTimeRange(1546300800, 1546300800))
+        ));
+        job.window.anchor = None;
+        job.validate().unwrap();
+
+        job.records.after = Some(TimeConvenience::Days(1));
+        job.records.before = Some(TimeConvenience::Days(2));
+        assert!(matches!(job.validate(), Err(PSError::InvalidTimeRange(..))));
+    }
+
+    #[test]
+    fn validate_edges() {
+        let mut job: ScrapeJob = toml::from_str(JOB).unwrap();
+        assert_eq!(job.output.edges_min_doc_count, 1);
+        job.output.edges = Some("gaming.edges.csv".into());
+        job.endpoint = PSEndpoint::Submission;
+        job.validate().unwrap();
+        job.endpoint = PSEndpoint::Subreddit;
+        assert!(matches!(
+            job.validate(),
+            Err(PSError::ConflictingParams(..))
+        ));
+    }
+
+    #[test]
+    fn reports_every_subreddit_problem() {
+        let mut job: ScrapeJob = toml::from_str(JOB).unwrap();
+        job.subreddits = vec![
+            "r/Gaming".into(),
+            "gaming?x=1".into(),
+            "gaming".into(),
+            "pc-gaming".into(),
+        ];
+        match job.validate() {
+            Err(PSError::SubredditChecks(problems)) => assert_eq!(problems.len(), 3),
+            other => panic!("{:?}", other),
+        }
+
+        job.subreddits = vec!["r/Gaming".into(), "Games".into()];
+        assert_eq!(job.resolve(0).subreddits, vec!["gaming", "games"]);
+    }
+}
@@ -0,0 +1,48 @@
+//! A small client for the Pushshift API built to sample Redditors from sets of subreddits.
+//!
+//! The crate is split along the steps of a scrape:
+//!
+//! * [pushshift] builds and validates queries, e.g. [PushshiftBuilder].
+//! * [scraperclient] pages through queries with [ScraperClient], filters what arrives, reports
+//!   progress to a [ScrapeObserver], and hashes names with [NameHasher].
+//! * [nodecsv] reads, writes, and merges node files in the formats of [NodeFormat].
+//! * [job] describes a complete scrape as a [ScrapeJob] which may be read from TOML or JSON.
+//! * [manifest] records how a dataset was produced and verifies its files.
+//! * [commands] runs jobs and the other subcommands of the command line tool.
+//!
+//! Queries are plain URLs so they can be built and inspected without a network connection:
+//!
+//! ```
+//! use thesis_gamer_scraper::{PSEndpoint, PushshiftBuilder};
+//!
+//! let url = PushshiftBuilder::new(PSEndpoint::Comment)
+//!     .subreddit("r/Gaming")?
+//!     .size(100)?
+//!     .build()?;
+//! assert!(url.as_str().contains("subreddit=gaming"));
+//! # Ok::<(), thesis_gamer_scraper::PSError>(())
+//! ```
+
+pub mod commands;
+pub mod job;
+pub mod manifest;
+pub mod nodecsv;
+pub mod pushshift;
+pub mod scraperclient;
+#[cfg(test)]
+mod testdir;
+
+pub use job::ScrapeJob;
+pub use nodecsv::{
+    merge::{MergeKey, Merger},
+    nodeio::NodeFormat,
+};
+pub use pushshift::{Comparison, PSEndpoint, PSError, PushshiftBuilder};
+pub use scraperclient::{
+    bots::BotFilter,
+    client::ScraperClient,
+    events::{ScrapeEvent, ScrapeObserver},
+    hashing::NameHasher,
+    nodefilter::{FilterPipeline, NodeFilter},
+    nodestructs::{Edge, Node, RawNode},
+};
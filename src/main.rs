@@ -1,69 +1,360 @@
-#![feature(once_cell)]
-pub mod nodecsv;
-pub mod pushshift;
-pub mod scraperclient;
+use clap::{Args, Parser, Subcommand};
+use std::{error::Error, fs, path::PathBuf, process::ExitCode};
+use thesis_gamer_scraper::{
+    commands,
+    job::{
+        Bots, Filters, Limits, Output, Records, ScrapeJob, Window, DEFAULT_SCRAPE, DEFAULT_TIMEOUT,
+    },
+    nodecsv::{merge::MergeKey, nodeio::NodeFormat},
+    pushshift::{
+        timeconvenience::TimeConvenience, Comparison, PSEndpoint, PSError, MAX_PS_FETCH_SIZE,
+    },
+    scraperclient::{
+        cache::{CacheMode, ResponseCache},
+        client::DEFAULT_BACKOFF,
+        nodestructs::OutputMode,
+        quota::Quota,
+        stop::StopCondition,
+    },
+};
 
-use clap::Parser;
-use log::{error, info};
-use pushshift::{PSEndpoint, PSError, PushshiftBuilder, MAX_PS_FETCH_SIZE};
-use scraperclient::client::ScraperClient;
-use std::path::PathBuf;
-
-// Default number of items to scrape
-static DEFAULT_SCRAPE: usize = 125000;
-static DEFAULT_TIMEOUT: u64 = 90;
-
-#[derive(Parser)]
-#[clap(author, version, about, long_about = None)]
+#[derive(Args)]
 struct ScrapeOpts {
-    /// Path to resume scrape from or where to save a new scrape.
-    #[clap(required = true, value_parser)]
-    path: PathBuf,
-    /// Subreddits to scrape
-    #[clap(required = true, value_parser)]
+    /// Run the scrape described by this TOML or JSON job file instead
+    #[clap(long, value_parser, exclusive = true)]
+    job: Option<PathBuf>,
+    /// Where to save the scrape
+    #[clap(required_unless_present = "job", value_parser)]
+    path: Option<PathBuf>,
+    /// Subreddits to scrape, optionally prefixed by r/
+    #[clap(required_unless_present = "job", value_parser)]
     subs: Vec<String>,
+    /// Check that every subreddit has items before scraping
+    #[clap(long, value_parser)]
+    check_subreddits: bool,
     /// Amount of nodes to scrape
     #[clap(default_value_t = DEFAULT_SCRAPE, short, long, value_parser)]
     amount: usize,
     /// Timeout to wait for each individual request
     #[clap(default_value_t = DEFAULT_TIMEOUT, short, long, value_parser)]
     timeout: u64,
+    /// Seconds to sleep between requests
+    #[clap(default_value_t = DEFAULT_BACKOFF, long, value_parser)]
+    backoff: u64,
+    /// Format of the scraped nodes: csv or ndjson
+    #[clap(long, default_value = "csv", value_parser)]
+    format: NodeFormat,
+    /// Key names' hashes with the contents of this file
+    #[clap(long, value_parser)]
+    hash_key: Option<PathBuf>,
+    /// Write names as they are so that they can be hashed later with the hash command
+    #[clap(long, value_parser, conflicts_with = "hash-key")]
+    no_hash: bool,
+    /// Also write every field of each node, including bodies, next to the output as ndjson
+    #[clap(long, value_parser, requires = "no-hash")]
+    raw: bool,
+    /// Endpoint to scrape: comment, submission, or subreddit
+    #[clap(long, default_value = "comment", value_parser)]
+    endpoint: PSEndpoint,
+    /// Items requested per page
+    #[clap(long, default_value_t = MAX_PS_FETCH_SIZE, value_parser)]
+    size: u32,
+    /// Only scrape items matching this search query
+    #[clap(short, long, value_parser)]
+    query: Option<String>,
+    /// Only scrape items by these authors. May be repeated or comma separated
+    #[clap(long, value_parser, use_value_delimiter = true)]
+    author: Vec<String>,
+    /// Only scrape items with this score, e.g. ">10", "<0", "5", or "5..20"
+    #[clap(long, value_parser, allow_hyphen_values = true)]
+    score: Option<Comparison>,
+    /// Only scrape submissions with this many comments. Takes the same format as score
+    #[clap(long, value_parser, allow_hyphen_values = true)]
+    num_comments: Option<Comparison>,
+    /// Snowball each author's subreddit counts into a weighted edge list at this path
+    #[clap(short, long, value_parser)]
+    edges: Option<PathBuf>,
+    /// Leave out edges to subreddits where an author has fewer items than this
+    #[clap(long, default_value_t = 1, value_parser, requires = "edges")]
+    edges_min_doc_count: u32,
+    /// Only scrape items created at or after this time. Takes epochs, ISO-8601 dates, or times
+    /// relative to the start of the scrape such as 30d
+    #[clap(long, value_parser)]
+    after: Option<TimeConvenience>,
+    /// Only scrape items created before this time. Takes the same formats as after
+    #[clap(long, value_parser)]
+    before: Option<TimeConvenience>,
+    /// Page forwards from the oldest item instead of backwards from the newest
+    #[clap(long, value_parser)]
+    ascending: bool,
+    /// Split [after, before) into this many equal time buckets per subreddit and sample each
+    #[clap(long, value_parser, requires_all = &["after", "before"])]
+    strata: Option<u32>,
+    /// Allocate each subreddit's nodes to buckets in proportion to their sizes
+    #[clap(long, value_parser, requires = "strata")]
+    proportional: bool,
+    /// Stop taking nodes from a subreddit once it reaches this many nodes
+    #[clap(long, value_parser, conflicts_with_all = &["min-quota", "proportional-quota", "strata"])]
+    fixed_quota: Option<usize>,
+    /// Reserve this many of the nodes for each subreddit
+    #[clap(long, value_parser, conflicts_with_all = &["proportional-quota", "strata"])]
+    min_quota: Option<usize>,
+    /// Split the nodes between subreddits in proportion to their sizes
+    #[clap(long, value_parser, conflicts_with = "strata")]
+    proportional_quota: bool,
+    /// Stop once this condition is met instead of after amount nodes, e.g.
+    /// "authors=5000|elapsed=3600" or "nodes=100000&saturation=0.05". Terms are nodes, authors,
+    /// floor (epoch), elapsed (seconds), requests, and saturation (new authors per item)
+    #[clap(long, value_parser, conflicts_with = "strata")]
+    stop: Option<StopCondition>,
+    /// Keep items with this score. Unlike score, this is checked locally as items arrive
+    #[clap(long, value_parser, allow_hyphen_values = true)]
+    keep_score: Option<Comparison>,
+    /// Keep items created at or after this time. Takes the same formats as after
+    #[clap(long, value_parser)]
+    keep_after: Option<TimeConvenience>,
+    /// Keep items created before this time. Takes the same formats as after
+    #[clap(long, value_parser)]
+    keep_before: Option<TimeConvenience>,
+    /// Keep comments whose body has this many characters. Takes the same format as score
+    #[clap(long, value_parser)]
+    body_length: Option<Comparison>,
+    /// Drop items whose body was removed or deleted
+    #[clap(long, value_parser)]
+    drop_removed: bool,
+    /// Drop submissions marked NSFW
+    #[clap(long, value_parser)]
+    drop_nsfw: bool,
+    /// Keep items whose body or title matches this case insensitive regular expression
+    #[clap(long, value_parser)]
+    keyword: Option<String>,
+    /// Drop the accounts listed in this file, one per line, as bots. May be repeated
+    #[clap(long, value_parser)]
+    blocklist: Vec<PathBuf>,
+    /// Drop accounts whose names match this case insensitive regular expression, e.g. "bot$".
+    /// May be repeated
+    #[clap(long, value_parser)]
+    bot_pattern: Vec<String>,
+    /// Drop accounts that post more than this many items per hour
+    #[clap(long, value_parser)]
+    max_rate: Option<f64>,
+    /// Drop accounts that post the same body this many times
+    #[clap(long, value_parser)]
+    max_repeats: Option<u32>,
+    /// Drop accounts with items distinguished by moderators or admins
+    #[clap(long, value_parser)]
+    drop_distinguished: bool,
+    /// Store responses in this directory so that the scrape can be replayed offline
+    #[clap(long, value_parser)]
+    cache: Option<PathBuf>,
+    /// What to do with the cache: record, replay, or pass-through
+    #[clap(long, default_value = "record", value_parser, requires = "cache")]
+    cache_mode: CacheMode,
+}
+
+impl From<ScrapeOpts> for ScrapeJob {
+    fn from(arguments: ScrapeOpts) -> Self {
+        let quota = match (
+            arguments.fixed_quota,
+            arguments.min_quota,
+            arguments.proportional_quota,
+        ) {
+            (Some(target), _, _) => Some(Quota::Fixed(target)),
+            (_, Some(minimum), _) => Some(Quota::Minimum(minimum)),
+            (_, _, true) => Some(Quota::Proportional),
+            _ => None,
+        };
+
+        ScrapeJob {
+            subreddits: arguments.subs,
+            endpoint: arguments.endpoint,
+            amount: arguments.amount,
+            check_subreddits: arguments.check_subreddits,
+            stop: arguments.stop,
+            quota,
+            output: Output {
+                path: arguments.path.unwrap_or_default(),
+                edges: arguments.edges,
+                edges_min_doc_count: arguments.edges_min_doc_count,
+                format: arguments.format,
+                mode: if arguments.raw {
+                    OutputMode::Raw
+                } else {
+                    OutputMode::Node
+                },
+                hash: !arguments.no_hash,
+                hash_key: arguments.hash_key,
+            },
+            window: Window {
+                after: arguments.after,
+                before: arguments.before,
+                anchor: None,
+                ascending: arguments.ascending,
+                strata: arguments.strata,
+                proportional: arguments.proportional,
+            },
+            filters: Filters {
+                query: arguments.query,
+                authors: arguments.author,
+                score: arguments.score,
+                num_comments: arguments.num_comments,
+            },
+            records: Records {
+                score: arguments.keep_score,
+                after: arguments.keep_after,
+                before: arguments.keep_before,
+                body_length: arguments.body_length,
+                removed: arguments.drop_removed,
+                nsfw: arguments.drop_nsfw,
+                keyword: arguments.keyword,
+            },
+            bots: Bots {
+                blocklists: arguments.blocklist,
+                patterns: arguments.bot_pattern,
+                max_rate: arguments.max_rate,
+                max_repeats: arguments.max_repeats,
+                distinguished: arguments.drop_distinguished,
+            },
+            limits: Limits {
+                size: arguments.size,
+                timeout: arguments.timeout,
+                backoff: arguments.backoff,
+            },
+            cache: arguments
+                .cache
+                .map(|directory| ResponseCache::new(directory, arguments.cache_mode)),
+        }
+    }
+}
+
+static EXIT_CODES: &str = "\
+EXIT CODES:
+    0     Success
+    2     Invalid arguments or job file
+    65    Malformed input data, such as node files that can't be merged or a dataset that
+          doesn't match its manifest
+    69    Pushshift refused or failed a request, or a replayed request wasn't recorded
+    70    Internal error
+    74    Failed to read or write a file
+    75    Temporary failure such as a timeout or rate limit; try again later";
+
+#[derive(Parser)]
+#[clap(author, version, about, long_about = None, after_help = EXIT_CODES)]
+struct Cli {
+    /// Draw a progress line with the scraped nodes and an ETA while scraping
+    #[clap(long, value_parser)]
+    progress: bool,
+    #[clap(subcommand)]
+    command: Command,
+}
+
+/// Node files are read and written as ndjson if their extension is .ndjson or .jsonl and as CSV
+/// otherwise.
+#[derive(Subcommand)]
+enum Command {
+    /// Start a new scrape. Fails if the output already exists
+    Scrape(Box<ScrapeOpts>),
+    /// Continue a scrape from a job file such as the resolved job written next to its output
+    Resume {
+        #[clap(value_parser)]
+        job: PathBuf,
+    },
+    /// Hash the names in a node file that was scraped with --no-hash
+    Hash {
+        #[clap(value_parser)]
+        input: PathBuf,
+        #[clap(value_parser)]
+        output: PathBuf,
+        /// Key the hashes with the contents of this file
+        #[clap(long, value_parser)]
+        key: Option<PathBuf>,
+    },
+    /// Convert a node file to the format implied by the output's extension
+    Export {
+        #[clap(value_parser)]
+        input: PathBuf,
+        #[clap(value_parser)]
+        output: PathBuf,
+    },
+    /// Summarize a node file
+    Stats {
+        #[clap(value_parser)]
+        input: PathBuf,
+    },
+    /// Combine node files and drop duplicates. Hashed and raw files can't be mixed
+    Merge {
+        #[clap(value_parser)]
+        output: PathBuf,
+        #[clap(required = true, value_parser)]
+        inputs: Vec<PathBuf>,
+        /// Nodes are duplicates if they share this key: id or a comma separated list of author,
+        /// created_utc, permalink, and subreddit
+        #[clap(long, default_value = "id", value_parser)]
+        key: MergeKey,
+    },
+    /// Check the files of a dataset against the manifest written by its scrape
+    Verify {
+        /// Node file of the dataset
+        #[clap(value_parser)]
+        input: PathBuf,
+    },
+    /// Check a job file and print the queries it would run without scraping
+    Validate {
+        #[clap(value_parser)]
+        job: PathBuf,
+        /// Also ask the API whether every subreddit has items
+        #[clap(long, value_parser)]
+        check: bool,
+    },
 }
 
 #[tokio::main]
-async fn main() -> Result<(), PSError> {
+async fn main() -> ExitCode {
     if pretty_env_logger::try_init_timed().is_err() {
         eprintln!("Failed to initialize logger. Logging may be disabled.")
     };
 
-    // Argument handling
-    let arguments = ScrapeOpts::parse();
-    if arguments.subs.is_empty() {
-        error!("No subreddits supplied.");
-        Err(PSError::NoArguments)?
+    let cli = Cli::parse();
+    match run(cli.command, cli.progress).await {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(error) => {
+            eprintln!("error: {}", error);
+            let mut source = error.source();
+            while let Some(cause) = source {
+                eprintln!("  caused by: {}", cause);
+                source = cause.source();
+            }
+            ExitCode::from(error.exit_code())
+        }
     }
+}
 
-    // Build scrapers
-    let subreddit_urls = PushshiftBuilder::new(PSEndpoint::Comment)
-        .size(MAX_PS_FETCH_SIZE)?
-        .build_multiple(&arguments.subs)?;
-
-    info!("Subreddits list: {:#?}", arguments.subs);
-    let mut scraper = if arguments.path.exists() && arguments.path.is_file() {
-        info!("Resuming scrape at {}", &arguments.path.to_string_lossy());
-        ScraperClient::from_csv(arguments.timeout, &subreddit_urls, &arguments.path)?
-    } else {
-        info!("Beginning new scrape.");
-        ScraperClient::new(arguments.timeout, &subreddit_urls)?
-    };
-
-    info!("Scraping until {} nodes", arguments.amount);
-    scraper.scrape_until(arguments.amount).await?;
-    assert!(!scraper.view_nodes().is_empty());
-    // scraper.scrape_individ_users()?;
-    info!("Nodes scraped: {}", scraper.length_nodes());
-    info!("Hashing names for privacy.");
-    scraper.hash_names();
-    info!("Writing to CSV.");
-    scraper.to_csv(&arguments.path)
+async fn run(command: Command, progress: bool) -> Result<(), PSError> {
+    match command {
+        Command::Scrape(mut arguments) => {
+            let job = match arguments.job.take() {
+                Some(path) => ScrapeJob::from_file(path)?,
+                None => (*arguments).into(),
+            };
+            commands::scrape(&job, false, progress).await
+        }
+        Command::Resume { job } => {
+            commands::scrape(&ScrapeJob::from_file(job)?, true, progress).await
+        }
+        Command::Hash { input, output, key } => {
+            let key = key
+                .map(|key| fs::read(&key).map_err(PSError::reading(&key)))
+                .transpose()?;
+            commands::hash(input, output, key.as_deref())
+        }
+        Command::Export { input, output } => commands::export(input, output),
+        Command::Stats { input } => commands::stats(input),
+        Command::Merge {
+            output,
+            inputs,
+            key,
+        } => commands::merge(&inputs, output, key),
+        Command::Verify { input } => commands::verify(input),
+        Command::Validate { job, check } => commands::validate(job, check).await,
+    }
 }
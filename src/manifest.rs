@@ -0,0 +1,220 @@
+use ring::digest::{Context, SHA256};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::BTreeMap,
+    fs::{self, File},
+    io::{BufReader, ErrorKind, Read},
+    path::{Path, PathBuf},
+};
+
+use crate::{job::ScrapeJob, pushshift::PSError, scraperclient::nodestructs::Node};
+
+/// How a dataset was produced, written next to the nodes with a `.manifest.json` extension.
+///
+/// Resumed scrapes append a run while the job, subreddit ranges, hashing, and checksums always
+/// describe the files as they were last written.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Manifest {
+    /// Version of this crate that last wrote the dataset.
+    pub version: String,
+    /// The resolved job so that running it again scrapes the same window.
+    pub job: ScrapeJob,
+    pub runs: Vec<Run>,
+    /// Nodes and the range of their creation times per subreddit.
+    pub subreddits: BTreeMap<String, SubredditRange>,
+    /// Hashing scheme and key fingerprint if names were hashed.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub hashing: Option<HashingScheme>,
+    /// SHA256 of every file written by the last run keyed by file name, or by absolute path for
+    /// files outside the directory of the nodes.
+    pub files: BTreeMap<String, String>,
+}
+
+/// A single scrape or resumption of one.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Run {
+    /// Epochs of when the run started and finished.
+    pub started: u64,
+    pub finished: u64,
+    pub resumed: bool,
+    /// How the scrape ended, e.g. ReachedTarget, or the error that ended it.
+    pub outcome: String,
+    pub requests: u64,
+    /// Items dropped by each filter.
+    pub rejections: BTreeMap<String, usize>,
+}
+
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq, Serialize, Deserialize)]
+pub struct SubredditRange {
+    pub nodes: usize,
+    pub oldest: u64,
+    pub newest: u64,
+}
+
+#[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+pub struct HashingScheme {
+    /// HMAC-SHA256 or SHA256.
+    pub algorithm: String,
+    pub fingerprint: String,
+}
+
+/// Where the manifest of a node file is stored.
+pub fn manifest_path<P: AsRef<Path>>(nodes: P) -> PathBuf {
+    nodes.as_ref().with_extension("manifest.json")
+}
+
+/// Hex encoded SHA256 of a file's contents.
+pub fn file_sha256<P: AsRef<Path>>(path: P) -> Result<String, PSError> {
+    let checksum = || -> Result<String, PSError> {
+        let mut reader = BufReader::new(File::open(&path)?);
+        let mut context = Context::new(&SHA256);
+        let mut buffer = [0; 8192];
+        loop {
+            let read = reader.read(&mut buffer)?;
+            if read == 0 {
+                return Ok(hex::encode(context.finish()));
+            }
+            context.update(&buffer[..read]);
+        }
+    };
+    checksum().map_err(PSError::reading(&path))
+}
+
+impl Manifest {
+    /// Manifest of a dataset with no runs and no files yet.
+    pub fn new(job: ScrapeJob) -> Self {
+        Manifest {
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            job,
+            runs: Vec::new(),
+            subreddits: BTreeMap::new(),
+            hashing: None,
+            files: BTreeMap::new(),
+        }
+    }
+
+    /// Reads the manifest of a node file if it has one.
+    pub fn read<P: AsRef<Path>>(nodes: P) -> Result<Option<Self>, PSError> {
+        let path = manifest_path(nodes);
+        match fs::read_to_string(&path) {
+            Ok(contents) => serde_json::from_str(&contents)
+                .map(Some)
+                .map_err(PSError::reading(&path)),
+            Err(error) if error.kind() == ErrorKind::NotFound => Ok(None),
+            Err(error) => Err(PSError::reading(&path)(error)),
+        }
+    }
+
+    pub fn write<P: AsRef<Path>>(&self, nodes: P) -> Result<(), PSError> {
+        let path = manifest_path(nodes);
+        let contents = serde_json::to_string_pretty(self)?;
+        fs::write(&path, contents).map_err(PSError::writing(&path))
+    }
+
+    /// Records the subreddits and time ranges of `nodes`.
+    pub fn record_nodes<'a, I>(&mut self, nodes: I)
+    where
+        I: IntoIterator<Item = &'a Node>,
+    {
+        self.subreddits.clear();
+        for node in nodes {
+            let range = self
+                .subreddits
+                .entry(node.subreddit.to_lowercase())
+                .or_insert(SubredditRange {
+                    nodes: 0,
+                    oldest: node.created_utc,
+                    newest: node.created_utc,
+                });
+            range.nodes += 1;
+            range.oldest = range.oldest.min(node.created_utc);
+            range.newest = range.newest.max(node.created_utc);
+        }
+    }
+
+    /// Records the checksums of `files`. Files next to `nodes` are keyed by their names so that
+    /// the dataset can be moved while others are keyed by their absolute paths. Missing files
+    /// are skipped.
+    pub fn record_files<P, Q>(&mut self, nodes: P, files: &[Q]) -> Result<(), PSError>
+    where
+        P: AsRef<Path>,
+        Q: AsRef<Path>,
+    {
+        let directory = absolute_parent(nodes.as_ref());
+        self.files.clear();
+        for path in files.iter().map(AsRef::as_ref) {
+            if !path.is_file() {
+                continue;
+            }
+            let key = if absolute_parent(path) == directory {
+                file_name(path)
+            } else {
+                fs::canonicalize(path)
+                    .map_err(PSError::reading(path))?
+                    .to_string_lossy()
+                    .into_owned()
+            };
+            self.files.insert(key, file_sha256(path)?);
+        }
+        Ok(())
+    }
+
+    /// Compares the checksums of the files next to `nodes` with those recorded and returns a
+    /// description of each file that is missing or differs.
+    pub fn verify<P: AsRef<Path>>(&self, nodes: P) -> Result<Vec<String>, PSError> {
+        let directory = nodes.as_ref().parent().unwrap_or(Path::new(""));
+        let mut problems = Vec::new();
+        for (name, expected) in self.files.iter() {
+            let path = directory.join(name);
+            if !path.is_file() {
+                problems.push(format!("{} is missing", name));
+            } else if file_sha256(&path)? != *expected {
+                problems.push(format!("{} differs from the manifest", name));
+            }
+        }
+        Ok(problems)
+    }
+}
+
+// Parent directory of a path that may not exist yet or may be a bare file name.
+fn absolute_parent(path: &Path) -> Option<PathBuf> {
+    let parent = path
+        .parent()
+        .filter(|parent| !parent.as_os_str().is_empty());
+    fs::canonicalize(parent.unwrap_or(Path::new("."))).ok()
+}
+
+fn file_name(path: &Path) -> String {
+    path.file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testdir::TestDir;
+
+    #[test]
+    fn verifies_checksums() {
+        let directory = TestDir::new("manifest");
+        let nodes = directory.join("nodes.csv");
+        fs::write(&nodes, "author,created_utc\n").unwrap();
+
+        let job: ScrapeJob =
+            toml::from_str("subreddits = [\"gaming\"]\n[output]\npath = \"nodes.csv\"").unwrap();
+        let mut manifest = Manifest::new(job);
+        manifest.record_files(&nodes, &[&nodes]).unwrap();
+        manifest.write(&nodes).unwrap();
+
+        let manifest = Manifest::read(&nodes).unwrap().unwrap();
+        assert!(manifest.verify(&nodes).unwrap().is_empty());
+        fs::write(&nodes, "author,created_utc\nalice,1\n").unwrap();
+        assert_eq!(manifest.verify(&nodes).unwrap().len(), 1);
+        fs::remove_file(&nodes).unwrap();
+        assert_eq!(
+            manifest.verify(&nodes).unwrap(),
+            vec!["nodes.csv is missing"]
+        );
+    }
+}
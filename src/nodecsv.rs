@@ -1 +1,3 @@
+pub mod merge;
 pub mod nodeio;
+pub mod postfilter;
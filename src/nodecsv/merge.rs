@@ -0,0 +1,296 @@
+use log::warn;
+use std::{
+    collections::{HashMap, HashSet},
+    path::Path,
+    str::FromStr,
+};
+
+use super::nodeio::NodeFormat;
+use crate::{
+    pushshift::PSError,
+    scraperclient::{hashing::read_fingerprint, nodestructs::Node},
+};
+
+/// Field of a Node that may be part of a merge key.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Hash)]
+pub enum NodeField {
+    Author,
+    CreatedUTC,
+    Permalink,
+    Subreddit,
+}
+
+/// What makes two nodes the same item when merging.
+#[derive(Clone, Debug, Default, Eq, PartialEq, Hash)]
+pub enum MergeKey {
+    /// The item's fullname parsed from its permalink. Only raw nodes have one.
+    #[default]
+    Id,
+    /// The values of these fields.
+    Fields(Vec<NodeField>),
+}
+
+impl MergeKey {
+    fn key(&self, node: &Node) -> Option<String> {
+        match self {
+            MergeKey::Id => node.item_id(),
+            MergeKey::Fields(fields) => Some(
+                fields
+                    .iter()
+                    .map(|field| match field {
+                        NodeField::Author => node.author.clone(),
+                        NodeField::CreatedUTC => node.created_utc.to_string(),
+                        NodeField::Permalink => node.permalink.clone(),
+                        NodeField::Subreddit => node.subreddit.to_lowercase(),
+                    })
+                    .collect::<Vec<_>>()
+                    .join("\u{1f}"),
+            ),
+        }
+    }
+}
+
+/// Parses `id` or a comma separated list of author, created_utc, permalink, and subreddit.
+impl FromStr for MergeKey {
+    type Err = PSError;
+
+    fn from_str(key: &str) -> Result<Self, Self::Err> {
+        if key.trim() == "id" {
+            return Ok(MergeKey::Id);
+        }
+
+        key.split(',')
+            .map(|field| match field.trim() {
+                "author" => Ok(NodeField::Author),
+                "created_utc" => Ok(NodeField::CreatedUTC),
+                "permalink" => Ok(NodeField::Permalink),
+                "subreddit" => Ok(NodeField::Subreddit),
+                _ => Err(PSError::InvalidMergeKey(key.to_string())),
+            })
+            .collect::<Result<Vec<_>, _>>()
+            .map(MergeKey::Fields)
+    }
+}
+
+/// Whether the names in a node file are hashed and by which key.
+#[derive(Clone, Debug, Eq, PartialEq, Hash)]
+pub enum Hashing {
+    Raw,
+    /// Hashed with the key identified by the fingerprint if one was recorded.
+    Hashed(Option<String>),
+}
+
+/// What a single input added to the merge.
+#[derive(Clone, Debug, Default, Eq, PartialEq, Hash)]
+pub struct Contribution {
+    pub source: String,
+    pub read: usize,
+    /// Nodes that weren't in any earlier input.
+    pub added: usize,
+    /// Nodes identical to one from an earlier input.
+    pub duplicates: usize,
+    /// Nodes that share a key with an earlier node but differ otherwise. The earlier node is
+    /// kept.
+    pub conflicts: usize,
+}
+
+/// Unions node files while deduplicating them by a MergeKey.
+///
+/// Hashed and raw files can't be merged since the same author would appear twice. Neither can
+/// files that were hashed with different keys.
+#[derive(Clone, Debug, Default)]
+pub struct Merger {
+    key: MergeKey,
+    nodes: HashMap<String, Node>,
+    hashing: Option<Hashing>,
+    contributions: Vec<Contribution>,
+}
+
+impl Merger {
+    pub fn new(key: MergeKey) -> Self {
+        Merger {
+            key,
+            ..Default::default()
+        }
+    }
+
+    /// Reads and adds a node file in the format implied by its extension.
+    pub fn add_file<P: AsRef<Path>>(&mut self, path: P) -> Result<&Contribution, PSError> {
+        let nodes = NodeFormat::from_path(&path).read(&path)?;
+        let source = path.as_ref().to_string_lossy().to_string();
+        let hashing = match nodes.iter().filter(|node| node.is_hashed()).count() {
+            0 => Hashing::Raw,
+            hashed if hashed == nodes.len() => Hashing::Hashed(read_fingerprint(&path)?),
+            _ => return Err(PSError::MixedHashing(source)),
+        };
+        self.add_nodes(source, nodes, hashing)
+    }
+
+    /// Adds nodes from `source` which is only used for reporting.
+    pub fn add_nodes<I>(
+        &mut self,
+        source: String,
+        nodes: I,
+        hashing: Hashing,
+    ) -> Result<&Contribution, PSError>
+    where
+        I: IntoIterator<Item = Node>,
+    {
+        self.check_hashing(&source, hashing)?;
+
+        let mut contribution = Contribution {
+            source,
+            ..Default::default()
+        };
+        for node in nodes {
+            contribution.read += 1;
+            let key = self
+                .key
+                .key(&node)
+                .ok_or_else(|| PSError::NoItemId(contribution.source.clone()))?;
+
+            match self.nodes.get(&key) {
+                None => {
+                    contribution.added += 1;
+                    self.nodes.insert(key, node);
+                }
+                Some(existing) if Self::same_item(existing, &node) => contribution.duplicates += 1,
+                Some(existing) => {
+                    warn!(
+                        "Conflicting records for {} in {}: {:?} and {:?}",
+                        key, contribution.source, existing, node
+                    );
+                    contribution.conflicts += 1;
+                }
+            }
+        }
+
+        self.contributions.push(contribution);
+        Ok(self
+            .contributions
+            .last()
+            .expect("Contribution was just added"))
+    }
+
+    // Strata only describe how a node was sampled so they don't conflict.
+    fn same_item(node: &Node, other: &Node) -> bool {
+        node.author == other.author
+            && node.created_utc == other.created_utc
+            && node.permalink == other.permalink
+            && node.subreddit.eq_ignore_ascii_case(&other.subreddit)
+    }
+
+    fn check_hashing(&mut self, source: &str, hashing: Hashing) -> Result<(), PSError> {
+        let merged = match (self.hashing.take(), hashing) {
+            (None, hashing) => hashing,
+            (Some(Hashing::Raw), Hashing::Raw) => Hashing::Raw,
+            (Some(Hashing::Hashed(known)), Hashing::Hashed(other)) => match (known, other) {
+                (Some(known), Some(other)) if known != other => {
+                    self.hashing = Some(Hashing::Hashed(Some(known)));
+                    return Err(PSError::DifferentHashKeys(source.to_string()));
+                }
+                (known, other) => {
+                    if known.is_none() || other.is_none() {
+                        warn!(
+                            "Can't verify that {} was hashed with the same key as the other inputs.",
+                            source
+                        );
+                    }
+                    Hashing::Hashed(known.or(other))
+                }
+            },
+            (Some(hashing), _) => {
+                self.hashing = Some(hashing);
+                return Err(PSError::MixedHashing(source.to_string()));
+            }
+        };
+        self.hashing = Some(merged);
+        Ok(())
+    }
+
+    pub fn contributions(&self) -> &[Contribution] {
+        &self.contributions
+    }
+
+    /// Hashing shared by every input or None if nothing was added yet.
+    pub fn hashing(&self) -> Option<&Hashing> {
+        self.hashing.as_ref()
+    }
+
+    pub fn into_nodes(self) -> HashSet<Node> {
+        self.nodes.into_values().collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn node(author: &str, created_utc: u64, permalink: &str) -> Node {
+        Node {
+            author: author.to_string(),
+            created_utc,
+            permalink: permalink.to_string(),
+            subreddit: "gaming".to_string(),
+            stratum: None,
+        }
+    }
+
+    #[test]
+    fn dedupes_by_id() {
+        let mut merger = Merger::new(MergeKey::Id);
+        let first = vec![
+            node("alice", 1, "/r/gaming/comments/abc/title/def/"),
+            node("bob", 2, "/r/gaming/comments/abc/title/"),
+        ];
+        let second = vec![
+            node("alice", 1, "/r/gaming/comments/abc/title/def/"),
+            node("carl", 3, "/r/gaming/comments/abc/title/def/"),
+            node("dana", 4, "/r/gaming/comments/xyz/title/ghi/"),
+        ];
+        merger
+            .add_nodes("first".into(), first, Hashing::Raw)
+            .unwrap();
+        let contribution = merger
+            .add_nodes("second".into(), second, Hashing::Raw)
+            .unwrap();
+        assert_eq!(
+            (
+                contribution.read,
+                contribution.added,
+                contribution.duplicates,
+                contribution.conflicts
+            ),
+            (3, 1, 1, 1)
+        );
+        assert_eq!(merger.into_nodes().len(), 3);
+    }
+
+    #[test]
+    fn refuses_mixed_hashing() {
+        let mut merger = Merger::new("author,created_utc".parse().unwrap());
+        merger
+            .add_nodes("raw".into(), vec![], Hashing::Raw)
+            .unwrap();
+        assert!(matches!(
+            merger.add_nodes("hashed".into(), vec![], Hashing::Hashed(None)),
+            Err(PSError::MixedHashing(_))
+        ));
+
+        let mut merger = Merger::new(MergeKey::Id);
+        let hashed = |fingerprint: &str| Hashing::Hashed(Some(fingerprint.to_string()));
+        merger.add_nodes("a".into(), vec![], hashed("a")).unwrap();
+        merger
+            .add_nodes("unknown".into(), vec![], Hashing::Hashed(None))
+            .unwrap();
+        assert!(matches!(
+            merger.add_nodes("b".into(), vec![], hashed("b")),
+            Err(PSError::DifferentHashKeys(_))
+        ));
+        assert!(matches!(
+            merger.add_nodes("id".into(), vec![node("a", 1, "NA")], hashed("a")),
+            Err(PSError::NoItemId(_))
+        ));
+        assert!("id,author".parse::<MergeKey>().is_err());
+    }
+}
@@ -1,8 +1,88 @@
 use csv::{Reader, Writer};
 use log::error;
-use std::{collections::HashSet, path::Path};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashSet,
+    fs::File,
+    io::{BufRead, BufReader, BufWriter, Write},
+    path::{Path, PathBuf},
+    str::FromStr,
+};
 
-use crate::{pushshift::PSError, scraperclient::nodestructs::Node};
+use super::postfilter::PostFilter;
+use crate::{
+    pushshift::PSError,
+    scraperclient::{
+        bots::BotRecord,
+        nodestructs::{Edge, Node, RawNode},
+        stratified::Stratum,
+    },
+};
+
+/// File format for scraped nodes.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum NodeFormat {
+    #[default]
+    Csv,
+    /// Newline delimited JSON with one node per line.
+    Ndjson,
+}
+
+impl NodeFormat {
+    /// Format implied by the extension of `path`: ndjson for `.ndjson` or `.jsonl` and CSV
+    /// otherwise.
+    pub fn from_path<P>(path: P) -> Self
+    where
+        P: AsRef<Path>,
+    {
+        match path
+            .as_ref()
+            .extension()
+            .and_then(|extension| extension.to_str())
+        {
+            Some(extension)
+                if extension.eq_ignore_ascii_case("ndjson")
+                    || extension.eq_ignore_ascii_case("jsonl") =>
+            {
+                NodeFormat::Ndjson
+            }
+            _ => NodeFormat::Csv,
+        }
+    }
+
+    pub fn read<P>(&self, path: P) -> Result<HashSet<Node>, PSError>
+    where
+        P: AsRef<Path>,
+    {
+        match self {
+            NodeFormat::Csv => read_nodes(path),
+            NodeFormat::Ndjson => read_nodes_ndjson(path),
+        }
+    }
+
+    pub fn write<P>(&self, path: P, nodes: &HashSet<Node>) -> Result<(), PSError>
+    where
+        P: AsRef<Path>,
+    {
+        match self {
+            NodeFormat::Csv => write_nodes(path, nodes),
+            NodeFormat::Ndjson => write_nodes_ndjson(path, nodes),
+        }
+    }
+}
+
+impl FromStr for NodeFormat {
+    type Err = PSError;
+
+    fn from_str(format: &str) -> Result<Self, Self::Err> {
+        match format.trim().to_lowercase().as_str() {
+            "csv" => Ok(NodeFormat::Csv),
+            "ndjson" => Ok(NodeFormat::Ndjson),
+            _ => Err(PSError::InvalidFormat(format.to_string())),
+        }
+    }
+}
 
 /// Reads all Nodes from a CSV file into a HashSet.
 /// Fails if the file cannot be read but reports errors while reading instead of failing.
@@ -11,37 +91,177 @@ where
     P: AsRef<Path>,
 {
     // Partition errors so that invalid rows don't cause the entire operation to fail.
-    let (nodes, errors): (Vec<_>, Vec<_>) = Reader::from_path(path)?
+    let (nodes, errors): (Vec<_>, Vec<_>) = Reader::from_path(&path)
+        .map_err(PSError::reading(&path))?
         .deserialize()
         .partition(|result| result.is_ok());
 
     for error in errors {
         let error = error.unwrap_err();
-
-        error!(
-            "Failed to parse row: {row:?}. Reason: {error}",
-            row = error
-                .position()
-                .map(|position| position.record())
-                .unwrap_or(u64::MAX),
-            error = error
-        );
+        let row = error
+            .position()
+            .map(|position| position.line())
+            .unwrap_or(u64::MAX);
+        let error = PSError::Row(path.as_ref().to_path_buf(), row, Box::new(error.into()));
+        error!("{}", error.report());
     }
 
     Ok(nodes.into_iter().map(|result| result.unwrap()).collect())
 }
 
-/// Writes all nodes to `path`.
+/// Reads Nodes from a CSV file while dropping those that don't match `filter`.
+pub fn read_nodes_filtered<P>(path: P, filter: &PostFilter) -> Result<HashSet<Node>, PSError>
+where
+    P: AsRef<Path>,
+{
+    let mut nodes = read_nodes(path)?;
+    nodes.retain(|node| filter.matches_node(node));
+    Ok(nodes)
+}
+
+/// Reads Nodes from newline delimited JSON. Like read_nodes(), invalid lines are reported
+/// instead of failing.
+pub fn read_nodes_ndjson<P>(path: P) -> Result<HashSet<Node>, PSError>
+where
+    P: AsRef<Path>,
+{
+    read_lines(path, |_| true)
+}
+
+/// Reads a newline delimited JSON dump of comments or submissions, keeping RawNodes that match
+/// `filter`. Like read_nodes(), invalid lines are reported instead of failing.
+pub fn read_dump<P>(path: P, filter: &PostFilter) -> Result<HashSet<RawNode>, PSError>
+where
+    P: AsRef<Path>,
+{
+    read_lines(path, |node| filter.matches_raw(node))
+}
+
+// Deserializes each line as JSON and keeps the rows that `keep` accepts.
+fn read_lines<P, T, F>(path: P, keep: F) -> Result<HashSet<T>, PSError>
+where
+    P: AsRef<Path>,
+    T: for<'de> Deserialize<'de> + Eq + std::hash::Hash,
+    F: Fn(&T) -> bool,
+{
+    let mut rows = HashSet::new();
+    let file = File::open(&path).map_err(PSError::reading(&path))?;
+    for (row, line) in (1..).zip(BufReader::new(file).lines()) {
+        let line = line.map_err(PSError::reading(&path))?;
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        match serde_json::from_str::<T>(&line) {
+            Ok(parsed) if keep(&parsed) => {
+                rows.insert(parsed);
+            }
+            Ok(_) => {}
+            Err(error) => {
+                let error = PSError::Row(path.as_ref().to_path_buf(), row, Box::new(error.into()));
+                error!("{}", error.report());
+            }
+        }
+    }
+
+    Ok(rows)
+}
+
+/// Writes all nodes to `path` sorted so that the same nodes always make the same file.
 pub fn write_nodes<P>(path: P, nodes: &HashSet<Node>) -> Result<(), PSError>
 where
     P: AsRef<Path>,
 {
-    let mut writer = Writer::from_path(path)?;
-    for node in nodes.iter() {
-        if let Some(error) = writer.serialize(node).err() {
-            error!("Failed to write a row: {error}");
+    write_rows(path, sorted(nodes))
+}
+
+/// Writes all nodes to `path` as sorted newline delimited JSON.
+pub fn write_nodes_ndjson<P>(path: P, nodes: &HashSet<Node>) -> Result<(), PSError>
+where
+    P: AsRef<Path>,
+{
+    write_lines(path, sorted(nodes))
+}
+
+/// Where the items of a scrape with OutputMode::Raw are written.
+pub fn raw_path<P: AsRef<Path>>(nodes: P) -> PathBuf {
+    nodes.as_ref().with_extension("raw.ndjson")
+}
+
+/// Writes items with every field they were requested with to `path` as sorted newline
+/// delimited JSON. Read them back with read_dump().
+pub fn write_raw_nodes<P>(path: P, raws: &[RawNode]) -> Result<(), PSError>
+where
+    P: AsRef<Path>,
+{
+    let mut raws: Vec<_> = raws.iter().collect();
+    raws.sort_unstable();
+    write_lines(path, raws)
+}
+
+// Serializes each row as a line of JSON.
+fn write_lines<'a, P, I, T>(path: P, rows: I) -> Result<(), PSError>
+where
+    P: AsRef<Path>,
+    I: IntoIterator<Item = &'a T>,
+    T: Serialize + 'a,
+{
+    let write = || -> Result<(), PSError> {
+        let mut writer = BufWriter::new(File::create(&path)?);
+        for row in rows {
+            serde_json::to_writer(&mut writer, row)?;
+            writer.write_all(b"\n")?;
+        }
+        Ok(writer.flush()?)
+    };
+
+    write().map_err(PSError::writing(&path))
+}
+
+fn sorted(nodes: &HashSet<Node>) -> Vec<&Node> {
+    let mut nodes: Vec<_> = nodes.iter().collect();
+    nodes.sort_unstable();
+    nodes
+}
+
+/// Writes weighted author to subreddit edges to `path`.
+pub fn write_edges<P>(path: P, edges: &[Edge]) -> Result<(), PSError>
+where
+    P: AsRef<Path>,
+{
+    write_rows(path, edges)
+}
+
+/// Writes the strata of a stratified sample to `path`.
+pub fn write_strata<P>(path: P, strata: &[Stratum]) -> Result<(), PSError>
+where
+    P: AsRef<Path>,
+{
+    write_rows(path, strata)
+}
+
+/// Writes the accounts dropped as bots to `path`.
+pub fn write_bots<P>(path: P, bots: &[BotRecord]) -> Result<(), PSError>
+where
+    P: AsRef<Path>,
+{
+    write_rows(path, bots)
+}
+
+// Serializes each row as CSV. Like read_nodes(), invalid rows are reported instead of failing.
+fn write_rows<'a, P, I, T>(path: P, rows: I) -> Result<(), PSError>
+where
+    P: AsRef<Path>,
+    I: IntoIterator<Item = &'a T>,
+    T: Serialize + 'a,
+{
+    let mut writer = Writer::from_path(&path).map_err(PSError::writing(&path))?;
+    for (row, record) in (1..).zip(rows) {
+        if let Some(error) = writer.serialize(record).err() {
+            let error = PSError::Row(path.as_ref().to_path_buf(), row, Box::new(error.into()));
+            error!("{}", error.report());
         }
     }
 
-    Ok(writer.flush()?)
+    writer.flush().map_err(PSError::writing(&path))
 }
@@ -0,0 +1,79 @@
+use crate::{
+    pushshift::Comparison,
+    scraperclient::nodestructs::{Node, RawNode},
+};
+
+/// Comparisons applied locally to nodes read from existing files or dumps.
+/// Unset comparisons always match.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct PostFilter {
+    pub created_utc: Option<Comparison>,
+    pub num_comments: Option<Comparison>,
+    pub score: Option<Comparison>,
+}
+
+impl PostFilter {
+    fn check(comparison: Option<Comparison>, value: i64) -> bool {
+        comparison.is_none_or(|comparison| comparison.matches(value))
+    }
+
+    /// Nodes only retain the creation time so other comparisons are ignored.
+    pub fn matches_node(&self, node: &Node) -> bool {
+        Self::check(self.created_utc, node.created_utc as i64)
+    }
+
+    /// Items missing a compared field, e.g. comments lack num_comments, don't match.
+    pub fn matches_raw(&self, raw: &RawNode) -> bool {
+        Self::check(self.created_utc, raw.created_utc as i64)
+            && self.score.is_none_or(|comparison| {
+                raw.score()
+                    .is_some_and(|score| comparison.matches(score.into()))
+            })
+            && self.num_comments.is_none_or(|comparison| {
+                raw.num_comments()
+                    .is_some_and(|comments| comparison.matches(comments.into()))
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn raw(value: serde_json::Value) -> RawNode {
+        let mut item = serde_json::json!({
+            "author": "link",
+            "created_utc": 100,
+            "permalink": "/r/gaming/comments/abc/title/",
+            "subreddit": "gaming",
+        });
+        item.as_object_mut()
+            .unwrap()
+            .extend(value.as_object().unwrap().clone());
+        serde_json::from_value(item).unwrap()
+    }
+
+    #[test]
+    fn compares_present_fields() {
+        let filter = PostFilter {
+            created_utc: Some(Comparison::Range(50, 150)),
+            num_comments: None,
+            score: Some(Comparison::Gt(1)),
+        };
+        assert!(filter.matches_raw(&raw(serde_json::json!({"score": 5}))));
+        assert!(!filter.matches_raw(&raw(serde_json::json!({"score": 1}))));
+        assert!(!filter.matches_raw(&raw(serde_json::json!({}))));
+        assert!(!filter.matches_raw(&raw(serde_json::json!({"created_utc": 200, "score": 5}))));
+
+        let submissions = PostFilter {
+            num_comments: Some(Comparison::Lt(10)),
+            ..Default::default()
+        };
+        assert!(submissions.matches_raw(&raw(serde_json::json!({"num_comments": 3}))));
+        assert!(!submissions.matches_raw(&raw(serde_json::json!({}))));
+
+        let node: Node = raw(serde_json::json!({"score": 0})).into();
+        assert!(filter.matches_node(&node));
+        assert!(PostFilter::default().matches_node(&node));
+    }
+}
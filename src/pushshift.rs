@@ -1,9 +1,13 @@
+mod comparison;
 mod psendpoint;
 mod pserror;
 mod pushshiftbuilder;
 pub mod sortopts;
+mod subreddit;
 pub mod timeconvenience;
 
+pub use comparison::Comparison;
 pub use psendpoint::PSEndpoint;
 pub use pserror::{PSError, MAX_PS_FETCH_SIZE};
-pub use pushshiftbuilder::PushshiftBuilder;
+pub use pushshiftbuilder::{PushshiftBuilder, MAX_URL_LENGTH};
+pub use subreddit::normalize_subreddit;
@@ -0,0 +1,172 @@
+use serde::{Deserialize, Serialize};
+use std::{
+    fmt::{Display, Formatter, Result},
+    str::FromStr,
+};
+
+use super::pserror::PSError;
+
+/// Numeric comparison for parameters such as score, num_comments, or created_utc.
+/// Ranges are closed on both ends. Comparisons are serialized in their Display form.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Hash, Serialize, Deserialize)]
+#[serde(into = "String", try_from = "String")]
+pub enum Comparison {
+    Gt(i64),
+    Lt(i64),
+    Eq(i64),
+    Range(i64, i64),
+}
+
+impl Comparison {
+    /// Checks `value` locally, i.e. for filtering nodes that were already scraped.
+    pub fn matches(&self, value: i64) -> bool {
+        use Comparison::*;
+        match *self {
+            Gt(thresh) => value > thresh,
+            Lt(thresh) => value < thresh,
+            Eq(thresh) => value == thresh,
+            Range(low, high) => (low..=high).contains(&value),
+        }
+    }
+
+    /// Ranges where the lower bound is higher than the upper bound can't match anything.
+    pub fn is_empty(&self) -> bool {
+        matches!(self, Comparison::Range(low, high) if low > high)
+    }
+
+    /// Renders the comparison as the API's query values.
+    /// The API only understands >, <, and equality so closed ranges are expressed as two
+    /// exclusive bounds which yields the same integers.
+    pub fn to_query(&self) -> Vec<String> {
+        use Comparison::*;
+        match *self {
+            Gt(thresh) => vec![format!(">{}", thresh)],
+            Lt(thresh) => vec![format!("<{}", thresh)],
+            Eq(thresh) => vec![thresh.to_string()],
+            Range(low, high) => vec![
+                format!(">{}", low.saturating_sub(1)),
+                format!("<{}", high.saturating_add(1)),
+            ],
+        }
+    }
+
+    /// Exclusive (after, before) epochs for time based comparisons.
+    pub fn to_epochs(&self) -> (Option<u64>, Option<u64>) {
+        use Comparison::*;
+        let epoch = |time: i64| u64::try_from(time).unwrap_or(0);
+        match *self {
+            Gt(time) => (Some(epoch(time)), None),
+            Lt(time) => (None, Some(epoch(time))),
+            Eq(time) => (
+                Some(epoch(time.saturating_sub(1))),
+                Some(epoch(time.saturating_add(1))),
+            ),
+            Range(low, high) => (
+                Some(epoch(low.saturating_sub(1))),
+                Some(epoch(high.saturating_add(1))),
+            ),
+        }
+    }
+}
+
+impl Display for Comparison {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        use Comparison::*;
+        match self {
+            Gt(thresh) => write!(f, ">{}", thresh),
+            Lt(thresh) => write!(f, "<{}", thresh),
+            Eq(thresh) => write!(f, "{}", thresh),
+            Range(low, high) => write!(f, "{}..{}", low, high),
+        }
+    }
+}
+
+/// Parses the Display form: `>10`, `<10`, `10`, or the closed range `5..20`.
+impl FromStr for Comparison {
+    type Err = PSError;
+
+    fn from_str(comparison: &str) -> std::result::Result<Self, Self::Err> {
+        let comparison = comparison.trim();
+        let invalid = || PSError::InvalidComparison(comparison.to_string());
+        let parse = |value: &str| value.trim().parse::<i64>().map_err(|_| invalid());
+
+        if let Some(thresh) = comparison.strip_prefix('>') {
+            Ok(Comparison::Gt(parse(thresh)?))
+        } else if let Some(thresh) = comparison.strip_prefix('<') {
+            Ok(Comparison::Lt(parse(thresh)?))
+        } else if let Some((low, high)) = comparison.split_once("..") {
+            Ok(Comparison::Range(parse(low)?, parse(high)?))
+        } else {
+            Ok(Comparison::Eq(parse(comparison)?))
+        }
+    }
+}
+
+impl From<Comparison> for String {
+    fn from(comparison: Comparison) -> Self {
+        comparison.to_string()
+    }
+}
+
+impl TryFrom<String> for Comparison {
+    type Error = PSError;
+
+    fn try_from(comparison: String) -> std::result::Result<Self, Self::Error> {
+        comparison.parse()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_round_trips() {
+        for comparison in [
+            Comparison::Gt(10),
+            Comparison::Lt(-5),
+            Comparison::Eq(0),
+            Comparison::Range(-5, 20),
+        ] {
+            assert_eq!(
+                comparison.to_string().parse::<Comparison>().unwrap(),
+                comparison
+            );
+        }
+        assert!(matches!(
+            ">=10".parse::<Comparison>(),
+            Err(PSError::InvalidComparison(_))
+        ));
+        assert!("5..".parse::<Comparison>().is_err());
+    }
+
+    #[test]
+    fn matches_values() {
+        assert!(Comparison::Gt(10).matches(11));
+        assert!(!Comparison::Gt(10).matches(10));
+        assert!(Comparison::Lt(-5).matches(-6));
+        assert!(!Comparison::Lt(-5).matches(-5));
+        assert!(Comparison::Eq(0).matches(0));
+        assert!(!Comparison::Eq(0).matches(1));
+        assert!(Comparison::Range(5, 20).matches(5));
+        assert!(Comparison::Range(5, 20).matches(20));
+        assert!(!Comparison::Range(5, 20).matches(21));
+        assert!(!Comparison::Range(20, 5).matches(10));
+        assert!(Comparison::Range(20, 5).is_empty());
+    }
+
+    #[test]
+    fn epochs_saturate_at_the_limits() {
+        assert_eq!(Comparison::Eq(100).to_epochs(), (Some(99), Some(101)));
+        assert_eq!(Comparison::Range(10, 20).to_epochs(), (Some(9), Some(21)));
+        assert_eq!(
+            Comparison::Eq(i64::MAX).to_epochs(),
+            (Some(i64::MAX as u64 - 1), Some(i64::MAX as u64))
+        );
+        assert_eq!(
+            Comparison::Range(i64::MIN, i64::MAX).to_epochs(),
+            (Some(0), Some(i64::MAX as u64))
+        );
+        assert_eq!(Comparison::Lt(-1).to_epochs(), (None, Some(0)));
+    }
+}
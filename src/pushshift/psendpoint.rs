@@ -1,13 +1,32 @@
-use std::fmt::{Display, Error, Formatter};
+use std::{
+    fmt::{Display, Error, Formatter},
+    str::FromStr,
+};
 
-/// PushShift API endpoint.
-#[derive(Clone, Copy, Debug, Eq, PartialEq, Hash)]
+use serde::{Deserialize, Serialize};
+
+use super::pserror::PSError;
+
+/// PushShift API endpoint. Endpoints are serialized by name.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Hash, Serialize, Deserialize)]
+#[serde(into = "String", try_from = "String")]
 pub enum PSEndpoint {
     Comment,
     Submission,
     Subreddit,
 }
 
+impl PSEndpoint {
+    /// Short name of the endpoint as accepted by FromStr.
+    pub fn name(&self) -> &'static str {
+        match self {
+            PSEndpoint::Comment => "comment",
+            PSEndpoint::Submission => "submission",
+            PSEndpoint::Subreddit => "subreddit",
+        }
+    }
+}
+
 impl Display for PSEndpoint {
     fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), Error> {
         write!(
@@ -21,3 +40,31 @@ impl Display for PSEndpoint {
         )
     }
 }
+
+/// Parses the endpoint's name, i.e. `comment`, `submission`, or `subreddit`.
+impl FromStr for PSEndpoint {
+    type Err = PSError;
+
+    fn from_str(endpoint: &str) -> Result<Self, Self::Err> {
+        match endpoint.trim().to_lowercase().as_str() {
+            "comment" => Ok(PSEndpoint::Comment),
+            "submission" => Ok(PSEndpoint::Submission),
+            "subreddit" => Ok(PSEndpoint::Subreddit),
+            _ => Err(PSError::InvalidEndpoint(endpoint.to_string())),
+        }
+    }
+}
+
+impl From<PSEndpoint> for String {
+    fn from(endpoint: PSEndpoint) -> Self {
+        endpoint.name().to_string()
+    }
+}
+
+impl TryFrom<String> for PSEndpoint {
+    type Error = PSError;
+
+    fn try_from(endpoint: String) -> Result<Self, Self::Error> {
+        endpoint.parse()
+    }
+}
@@ -4,25 +4,69 @@ use std::{
     convert::From,
     error::Error,
     fmt::{Display, Formatter},
-    io::Error as IoError,
+    io::{Error as IoError, ErrorKind},
+    path::{Path, PathBuf},
 };
 use url::ParseError;
 
+use super::{comparison::Comparison, psendpoint::PSEndpoint, pushshiftbuilder::MAX_URL_LENGTH};
+
 pub static MAX_PS_FETCH_SIZE: u32 = 1000;
 
+/// Errors that wrap another error, such as Read or Request, only describe their context. The
+/// wrapped error is available through `source()` and `report()` prints the whole chain.
 #[derive(Debug)]
 pub enum PSError {
     AlreadyAdded(String),
+    ConflictingParams(String, String),
     Csv(CSVError),
+    DifferentHashKeys(String),
+    EmptyList(String),
+    EmptyQuery,
+    EmptyRange(Comparison),
     Io(IoError),
+    InvalidAuthor(String),
+    InvalidCacheMode(String),
+    InvalidComparison(String),
+    InvalidEndpoint(String),
+    InvalidField(String),
+    InvalidFormat(String),
+    InvalidId(String),
+    InvalidMergeKey(String),
+    InvalidPattern(String),
+    InvalidStopCondition(String),
     InvalidSubreddit(String),
+    InvalidTime(String),
+    InvalidTimeRange(u64, u64),
+    /// Files of a dataset that are missing or differ from its manifest.
+    ManifestMismatch(Vec<String>),
+    MixedHashing(String),
     NoArguments,
-    NoMoreNodes,
+    NoItemId(String),
+    NoManifest(String),
     NoParams,
+    /// URL of a request that was replayed without a recorded response.
+    NotCached(String),
+    OutputExists(String),
+    OutputMissing(String),
     Parse(ParseError),
+    Read(PathBuf, Box<PSError>),
+    Request(String, Box<PSError>),
     Reqwest(reqwest::Error),
+    RequiresParam(String, String),
+    /// Path, one-based row or line number, and why the row was rejected.
+    Row(PathBuf, u64, Box<PSError>),
     SerdeJson(SerdeJSONError),
     SizeTooHigh(u32),
+    /// URL and HTTP status of an unsuccessful response.
+    Status(String, u16),
+    Subreddit(String, Box<PSError>),
+    SubredditChecks(Vec<String>),
+    TomlDe(toml::de::Error),
+    TomlSer(toml::ser::Error),
+    UrlTooLong(String),
+    WrongEndpoint(String, PSEndpoint),
+    Write(PathBuf, Box<PSError>),
 }
 
 impl Display for PSError {
@@ -30,36 +74,290 @@ impl Display for PSError {
         use PSError::*;
         match self {
             AlreadyAdded(param) => write!(f, "Parameter already added: {}", param),
+            ConflictingParams(param, alias) => {
+                write!(f, "Parameter {} can't be combined with {}", param, alias)
+            }
             Csv(error) => write!(f, "CSV: {}", error),
+            DifferentHashKeys(source) => write!(
+                f,
+                "Names in {} were hashed with a different key than the other inputs",
+                source
+            ),
+            EmptyList(param) => write!(f, "Parameter {} requires at least one value", param),
+            EmptyQuery => write!(f, "Search query may not be empty."),
+            EmptyRange(range) => write!(f, "Range can't match anything: {}", range),
             Io(error) => write!(f, "IO: {}", error),
+            InvalidAuthor(author) => write!(
+                f,
+                "Usernames are 3-20 alphanumeric, _, or - characters: {}",
+                author
+            ),
+            InvalidCacheMode(mode) => write!(
+                f,
+                "Cache mode must be record, replay, or pass-through: {}",
+                mode
+            ),
+            InvalidComparison(comparison) => write!(
+                f,
+                "Comparisons are >N, <N, N, or an inclusive range such as 5..20: {}",
+                comparison
+            ),
+            InvalidEndpoint(endpoint) => write!(
+                f,
+                "Endpoint must be comment, submission, or subreddit: {}",
+                endpoint
+            ),
+            InvalidField(field) => write!(
+                f,
+                "Fields may only contain lowercase alphanumeric and _: {}",
+                field
+            ),
+            InvalidFormat(format) => write!(f, "Node format must be csv or ndjson: {}", format),
+            InvalidId(id) => write!(f, "IDs must be base36 with an optional t#_ prefix: {}", id),
+            InvalidMergeKey(key) => write!(
+                f,
+                "Merge keys are id or a comma separated list of author, created_utc, permalink, and subreddit: {}",
+                key
+            ),
+            InvalidPattern(pattern) => write!(f, "Invalid regular expression: {}", pattern),
+            InvalidStopCondition(condition) => write!(
+                f,
+                "Stop conditions are terms such as authors=5000 joined by & or |: {}",
+                condition
+            ),
             InvalidSubreddit(subreddit) => write!(
                 f,
-                "Subreddit may only contain alphanumeric and _: {}",
+                "Subreddits are 2-21 letters, digits, or _ and may not start with _: {}",
                 subreddit
             ),
+            InvalidTime(time) => write!(
+                f,
+                "Times are relative (30d, 2w), epochs, or ISO-8601 dates: {}",
+                time
+            ),
+            InvalidTimeRange(after, before) => write!(
+                f,
+                "After ({}) must be earlier than before ({})",
+                after, before
+            ),
+            ManifestMismatch(problems) => {
+                write!(f, "Dataset doesn't match its manifest:")?;
+                for problem in problems {
+                    write!(f, "\n  {}", problem)?;
+                }
+                Ok(())
+            }
+            MixedHashing(source) => write!(
+                f,
+                "Hashed and raw names can't be mixed but {} differs from the other inputs",
+                source
+            ),
             NoArguments => write!(f, "Expected subreddits to scrape."),
-            NoMoreNodes => write!(f, "No more nodes to scrape."),
+            NoItemId(source) => write!(
+                f,
+                "Nodes in {} have no item id. Merge hashed files by fields such as author,created_utc,subreddit",
+                source
+            ),
+            NoManifest(path) => write!(f, "No manifest found for {}", path),
             NoParams => write!(
                 f,
                 "No parameters found. You have to specify parameters such as a subreddit."
             ),
+            NotCached(url) => write!(f, "No recorded response to replay for {}", url),
+            OutputExists(path) => write!(
+                f,
+                "Output already exists: {}. Use resume to continue the scrape.",
+                path
+            ),
+            OutputMissing(path) => write!(
+                f,
+                "Output doesn't exist: {}. Use scrape to start a new scrape.",
+                path
+            ),
             Parse(error) => write!(f, "Parse: {}", error),
+            Read(path, _) => write!(f, "Failed to read {}", path.display()),
+            Request(url, _) => write!(f, "Request failed: {}", url),
             Reqwest(error) => write!(f, "Reqwest: {}", error),
+            RequiresParam(param, required) => {
+                write!(
+                    f,
+                    "Parameter {} requires {} to be set first",
+                    param, required
+                )
+            }
+            Row(path, row, _) => write!(f, "Skipped row {} of {}", row, path.display()),
             SerdeJson(error) => write!(f, "Serde: {}", error),
             SizeTooHigh(size) => write!(
                 f,
                 "Size must be less than {}; got: {}",
                 MAX_PS_FETCH_SIZE, size
             ),
+            Status(url, status) => write!(f, "HTTP {} from {}", status, url),
+            Subreddit(subreddit, _) => write!(f, "Failed on r/{}", subreddit),
+            SubredditChecks(problems) => {
+                write!(f, "Subreddit checks failed:")?;
+                for problem in problems {
+                    write!(f, "\n  {}", problem)?;
+                }
+                Ok(())
+            }
+            TomlDe(error) => write!(f, "TOML: {}", error),
+            TomlSer(error) => write!(f, "TOML: {}", error),
+            UrlTooLong(value) => write!(
+                f,
+                "Value doesn't fit into a URL of at most {} characters: {}",
+                MAX_URL_LENGTH, value
+            ),
+            WrongEndpoint(param, endpoint) => {
+                write!(f, "Parameter {} isn't supported by {}", param, endpoint)
+            }
+            Write(path, _) => write!(f, "Failed to write {}", path.display()),
         }
     }
 }
 
-impl Error for PSError {}
+impl PSError {
+    /// Wraps an error that occurred while reading `path`. Meant for `map_err`.
+    pub fn reading<P, E>(path: P) -> impl FnOnce(E) -> PSError
+    where
+        P: AsRef<Path>,
+        E: Into<PSError>,
+    {
+        let path = path.as_ref().to_path_buf();
+        move |error| PSError::Read(path, Box::new(error.into()))
+    }
 
+    /// Wraps an error that occurred while writing `path`. Meant for `map_err`.
+    pub fn writing<P, E>(path: P) -> impl FnOnce(E) -> PSError
+    where
+        P: AsRef<Path>,
+        E: Into<PSError>,
+    {
+        let path = path.as_ref().to_path_buf();
+        move |error| PSError::Write(path, Box::new(error.into()))
+    }
+
+    /// Wraps an error that occurred while requesting `url`. Meant for `map_err`.
+    pub fn requesting<E>(url: &str) -> impl FnOnce(E) -> PSError
+    where
+        E: Into<PSError>,
+    {
+        let url = url.to_string();
+        move |error| PSError::Request(url, Box::new(error.into()))
+    }
+
+    /// Wraps an error that occurred while handling `subreddit`. Meant for `map_err`.
+    pub fn for_subreddit<E>(subreddit: &str) -> impl FnOnce(E) -> PSError
+    where
+        E: Into<PSError>,
+    {
+        let subreddit = subreddit.to_string();
+        move |error| PSError::Subreddit(subreddit, Box::new(error.into()))
+    }
+
+    /// The error without its context.
+    pub fn root(&self) -> &PSError {
+        use PSError::*;
+        match self {
+            Read(_, error)
+            | Request(_, error)
+            | Row(_, _, error)
+            | Subreddit(_, error)
+            | Write(_, error) => error.root(),
+            _ => self,
+        }
+    }
+
+    /// The error followed by each of its sources separated by colons.
+    pub fn report(&self) -> String {
+        let mut report = self.to_string();
+        let mut source = self.source();
+        while let Some(error) = source {
+            report.push_str(": ");
+            report.push_str(&error.to_string());
+            source = error.source();
+        }
+        report
+    }
+
+    /// Whether trying again later may succeed. Timeouts, dropped connections, rate limits, and
+    /// server errors are retryable while everything else, such as invalid arguments or a 404,
+    /// will fail the same way again.
+    pub fn is_retryable(&self) -> bool {
+        match self.root() {
+            PSError::Io(error) => matches!(
+                error.kind(),
+                ErrorKind::Interrupted | ErrorKind::TimedOut | ErrorKind::WouldBlock
+            ),
+            PSError::Reqwest(error) => {
+                error.is_timeout()
+                    || error.is_connect()
+                    || error.is_body()
+                    || error
+                        .status()
+                        .is_some_and(|status| is_retryable_status(status.as_u16()))
+            }
+            PSError::Status(_, status) => is_retryable_status(*status),
+            _ => false,
+        }
+    }
+
+    /// Process exit code for the error, following sysexits.h where it applies.
+    ///
+    /// | Code | Meaning |
+    /// |------|---------|
+    /// | 2    | Invalid arguments or job file, like clap's usage errors |
+    /// | 65   | Malformed input data such as a node file that can't be merged or a dataset that doesn't match its manifest (EX_DATAERR) |
+    /// | 69   | Pushshift refused or failed a request or a replayed request wasn't recorded (EX_UNAVAILABLE) |
+    /// | 70   | Internal error such as an unserializable job (EX_SOFTWARE) |
+    /// | 74   | Failed to read or write a file (EX_IOERR) |
+    /// | 75   | Temporary failure such as a timeout or rate limit; try again later (EX_TEMPFAIL) |
+    pub fn exit_code(&self) -> u8 {
+        use PSError::*;
+        if self.is_retryable() {
+            return 75;
+        }
+        match self.root() {
+            Csv(_) | DifferentHashKeys(_) | ManifestMismatch(_) | MixedHashing(_) | NoItemId(_)
+            | NoManifest(_) | SerdeJson(_) | TomlDe(_) => 65,
+            NotCached(_) | Reqwest(_) | Status(..) => 69,
+            Parse(_) | TomlSer(_) => 70,
+            Io(_) => 74,
+            _ => 2,
+        }
+    }
+}
+
+// 408 Request Timeout, 429 Too Many Requests, and server errors.
+fn is_retryable_status(status: u16) -> bool {
+    status == 408 || status == 429 || (500..600).contains(&status)
+}
+
+impl Error for PSError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        use PSError::*;
+        match self {
+            Read(_, error)
+            | Request(_, error)
+            | Row(_, _, error)
+            | Subreddit(_, error)
+            | Write(_, error) => Some(error.as_ref()),
+            // Wrapped errors already include their causes in their messages.
+            _ => None,
+        }
+    }
+}
+
+// Failing to open or write a file is an I/O error even when the CSV crate reports it.
 impl From<CSVError> for PSError {
     fn from(error: CSVError) -> Self {
-        PSError::Csv(error)
+        if !error.is_io_error() {
+            return PSError::Csv(error);
+        }
+        match error.into_kind() {
+            csv::ErrorKind::Io(error) => PSError::Io(error),
+            _ => unreachable!("is_io_error() only holds for ErrorKind::Io"),
+        }
     }
 }
 
@@ -86,3 +384,40 @@ impl From<SerdeJSONError> for PSError {
         PSError::SerdeJson(error)
     }
 }
+
+impl From<toml::de::Error> for PSError {
+    fn from(error: toml::de::Error) -> Self {
+        PSError::TomlDe(error)
+    }
+}
+
+impl From<toml::ser::Error> for PSError {
+    fn from(error: toml::ser::Error) -> Self {
+        PSError::TomlSer(error)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn context_keeps_source_and_classification() {
+        let error = PSError::for_subreddit("gaming")(PSError::Status(
+            "https://api.pushshift.io".into(),
+            503,
+        ));
+        assert_eq!(
+            error.report(),
+            "Failed on r/gaming: HTTP 503 from https://api.pushshift.io"
+        );
+        assert!(error.is_retryable());
+        assert_eq!(error.exit_code(), 75);
+
+        let error = PSError::reading("nodes.csv")(IoError::from(ErrorKind::NotFound));
+        assert!(error.source().is_some());
+        assert!(!error.is_retryable());
+        assert_eq!(error.exit_code(), 74);
+        assert_eq!(PSError::NoArguments.exit_code(), 2);
+    }
+}
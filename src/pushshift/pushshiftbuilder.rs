@@ -1,50 +1,78 @@
 use regex::Regex;
 use reqwest::Url;
-use std::{collections::HashMap, sync::LazyLock};
+use std::{collections::BTreeMap, sync::LazyLock};
 
+use super::comparison::Comparison;
 use super::psendpoint::PSEndpoint;
 use super::pserror::PSError;
 use super::pserror::MAX_PS_FETCH_SIZE;
-use super::sortopts::{Parameter, Sort};
+use super::sortopts::{Aggregation, Distinguished, Frequency, Parameter, Sort};
+use super::subreddit::normalize_subreddit;
 use super::timeconvenience::TimeConvenience;
 
 // PushShift API
 static PUSHSHIFT: &str = "https://api.pushshift.io/reddit";
-// I tested the RegEx below so unwrap() is fine.
-static VALID_REDDIT: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"[\w\d_-]+").unwrap());
+// Batched URLs are kept under this length since longer URLs are rejected by some servers.
+pub static MAX_URL_LENGTH: usize = 2000;
+// I tested the RegExes below so unwrap() is fine.
+// Reddit usernames are 3 to 20 characters of letters, digits, _, and -.
+static VALID_AUTHOR: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"^[A-Za-z0-9_-]{3,20}$").unwrap());
+// Base36 IDs with an optional fullname prefix (t1_ for comments, t3_ for submissions, etc.).
+static VALID_ID: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"^(t[1-6]_)?[a-z0-9]+$").unwrap());
+// Field names as returned by the API such as created_utc or link_id.
+static VALID_FIELD: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"^[a-z0-9_]+$").unwrap());
 
 /// Builds a reqwest::Url for the PushShift Reddit API.
 #[derive(Clone, Debug)]
 pub struct PushshiftBuilder {
+    endpoint: PSEndpoint,
+    order: Sort,
     url: String,
-    params: HashMap<String, String>,
+    // BTreeMap keeps the query order stable which makes built URLs reproducible.
+    // Parameters may hold several values for comparisons such as closed ranges.
+    params: BTreeMap<String, Vec<String>>,
 }
 
 impl PushshiftBuilder {
     pub fn new(endpoint: PSEndpoint) -> Self {
         PushshiftBuilder {
+            endpoint,
+            order: Sort::Desc,
             url: format!("{PUSHSHIFT}{endpoint}"),
-            params: HashMap::new(),
+            params: BTreeMap::new(),
         }
     }
 
     /// Builds the PushShift API call provided that the caller specified some parameters.
     /// This function doesn't consume self to facilitate building new URLs using _replace_sub_.
     pub fn build(&mut self) -> Result<Url, PSError> {
-        // The params HashMap shouldn't be empty
+        // The params map shouldn't be empty
         if !self.params.is_empty() {
             // Sorting is always added so we can actually paginate the results as mentioned in the
             // sort() function.
-            self.sort(Sort::Desc, Parameter::CreatedUTC)
-                // Finally, check for "before" and add the parameter as the max u32 value if it
-                // doesn't exist. Using u32 max is safe (I checked), but the before() function
-                // takes in a u64 in case the API changes. I'm not entirely sure how epochs work
-                // but u64 seemed like the right idea. I check for "before" in case the caller
-                // provided it already.
-                .params
-                .entry("before".to_owned())
-                .or_insert_with(|| u32::MAX.to_string());
-            Ok(Url::parse_with_params(&self.url, &self.params)?)
+            self.sort(self.order, Parameter::CreatedUTC);
+            // Finally, check for the cursor parameter and add it if it doesn't exist. Descending
+            // scrapes page with "before" starting from the max u32 value. Using u32 max is safe
+            // (I checked), but the before() function takes in a u64 in case the API changes. I'm
+            // not entirely sure how epochs work but u64 seemed like the right idea. Ascending
+            // scrapes page with "after" starting from the epoch. I check for the aliases "until"
+            // and "since" in case the caller provided either already.
+            let (cursor, alias, start) = match self.order {
+                Sort::Desc => ("before", "until", u32::MAX.to_string()),
+                Sort::Asc => ("after", "since", 0.to_string()),
+            };
+            if !self.params.contains_key(alias) {
+                self.params
+                    .entry(cursor.to_owned())
+                    .or_insert_with(|| vec![start]);
+            }
+            Ok(Url::parse_with_params(
+                &self.url,
+                self.params
+                    .iter()
+                    .flat_map(|(param, values)| values.iter().map(move |value| (param, value))),
+            )?)
         } else {
             Err(PSError::NoParams)
         }
@@ -56,14 +84,198 @@ impl PushshiftBuilder {
             .collect()
     }
 
+    /// Builds as few URLs as possible by packing comma separated subreddits into each URL
+    /// while staying under MAX_URL_LENGTH.
+    pub fn build_batched<T: AsRef<str>>(&mut self, subs: &[T]) -> Result<Vec<Url>, PSError> {
+        self.build_batched_param("subreddit", subs, |builder, batch| {
+            builder.subreddits(batch).map(|_| ())
+        })
+    }
+
+    /// Like build_batched() but packs authors instead of subreddits.
+    pub fn build_batched_authors<T: AsRef<str>>(
+        &mut self,
+        authors: &[T],
+    ) -> Result<Vec<Url>, PSError> {
+        self.build_batched_param("author", authors, |builder, batch| {
+            builder.author(batch).map(|_| ())
+        })
+    }
+
     pub fn before(&mut self, time: TimeConvenience) -> Result<&mut Self, PSError> {
+        self.check_conflict("before", "until")?;
+        self.check_time_range(self.param_epoch("after", "since"), time.epoch())?;
         self.add_param("before", &time.to_string())
     }
 
     pub fn after(&mut self, time: TimeConvenience) -> Result<&mut Self, PSError> {
+        self.check_conflict("after", "since")?;
+        self.check_time_range(time.epoch(), self.param_epoch("before", "until"))?;
         self.add_param("after", &time.to_string())
     }
 
+    /// Restricts results to the half open window `[start, end)` of epochs.
+    pub fn window(&mut self, start: u64, end: u64) -> Result<&mut Self, PSError> {
+        if start >= end {
+            return Err(PSError::InvalidTimeRange(start, end));
+        }
+        // Windows are closed ranges of seconds so end is adjusted to be inclusive.
+        self.created_utc(Comparison::Range(
+            start.try_into().unwrap_or(i64::MAX),
+            (end - 1).try_into().unwrap_or(i64::MAX),
+        ))
+    }
+
+    /// Sets the direction used to paginate by creation time. Descending (newest first) is the
+    /// default. Ascending scrapes page forward with "after" cursors.
+    pub fn order(&mut self, order: Sort) -> Result<&mut Self, PSError> {
+        if self.params.contains_key("sort") {
+            Err(PSError::AlreadyAdded("sort".to_string()))
+        } else {
+            self.order = order;
+            Ok(self)
+        }
+    }
+
+    /// Newer alias for `after`. Only one of the two may be set.
+    pub fn since(&mut self, time: TimeConvenience) -> Result<&mut Self, PSError> {
+        self.check_conflict("since", "after")?;
+        self.check_time_range(time.epoch(), self.param_epoch("before", "until"))?;
+        self.add_param("since", &time.to_string())
+    }
+
+    /// Newer alias for `before`. Only one of the two may be set.
+    pub fn until(&mut self, time: TimeConvenience) -> Result<&mut Self, PSError> {
+        self.check_conflict("until", "before")?;
+        self.check_time_range(self.param_epoch("after", "since"), time.epoch())?;
+        self.add_param("until", &time.to_string())
+    }
+
+    /// Full text search query.
+    pub fn q(&mut self, query: &str) -> Result<&mut Self, PSError> {
+        if query.trim().is_empty() {
+            Err(PSError::EmptyQuery)
+        } else {
+            self.add_param("q", query)
+        }
+    }
+
+    /// Restricts results to one or more authors.
+    pub fn author<T: AsRef<str>>(&mut self, authors: &[T]) -> Result<&mut Self, PSError> {
+        let authors =
+            Self::validate_list("author", authors, &VALID_AUTHOR, PSError::InvalidAuthor)?;
+        self.add_param("author", &authors)
+    }
+
+    /// Restricts comments to a single submission.
+    pub fn link_id(&mut self, id: &str) -> Result<&mut Self, PSError> {
+        self.require_endpoint("link_id", PSEndpoint::Comment)?;
+        let id = Self::validate_list("link_id", &[id], &VALID_ID, PSError::InvalidId)?;
+        self.add_param("link_id", &id)
+    }
+
+    /// Restricts comments to direct replies of a comment or submission.
+    pub fn parent_id(&mut self, id: &str) -> Result<&mut Self, PSError> {
+        self.require_endpoint("parent_id", PSEndpoint::Comment)?;
+        let id = Self::validate_list("parent_id", &[id], &VALID_ID, PSError::InvalidId)?;
+        self.add_param("parent_id", &id)
+    }
+
+    /// Fetches specific items by their base36 IDs.
+    pub fn ids<T: AsRef<str>>(&mut self, ids: &[T]) -> Result<&mut Self, PSError> {
+        let ids = Self::validate_list("ids", ids, &VALID_ID, PSError::InvalidId)?;
+        self.add_param("ids", &ids)
+    }
+
+    /// Restricts the returned objects to the listed fields.
+    pub fn fields<T: AsRef<str>>(&mut self, fields: &[T]) -> Result<&mut Self, PSError> {
+        let fields = Self::validate_list("fields", fields, &VALID_FIELD, PSError::InvalidField)?;
+        self.add_param("fields", &fields)
+    }
+
+    /// Restricts submissions by their number of comments.
+    pub fn num_comments(&mut self, comparison: Comparison) -> Result<&mut Self, PSError> {
+        self.require_endpoint("num_comments", PSEndpoint::Submission)?;
+        self.add_comparison("num_comments", comparison)
+    }
+
+    /// Restricts results by score.
+    pub fn score(&mut self, comparison: Comparison) -> Result<&mut Self, PSError> {
+        self.add_comparison("score", comparison)
+    }
+
+    /// Restricts results by creation time using the `after` and `before` parameters.
+    pub fn created_utc(&mut self, comparison: Comparison) -> Result<&mut Self, PSError> {
+        if comparison.is_empty() {
+            return Err(PSError::EmptyRange(comparison));
+        }
+
+        let (after, before) = comparison.to_epochs();
+        if let Some(after) = after {
+            self.after(TimeConvenience::UTC(after))?;
+        }
+        if let Some(before) = before {
+            self.before(TimeConvenience::UTC(before))?;
+        }
+        Ok(self)
+    }
+
+    /// Restricts submissions to NSFW or SFW posts.
+    pub fn over_18(&mut self, nsfw: bool) -> Result<&mut Self, PSError> {
+        self.require_endpoint("over_18", PSEndpoint::Submission)?;
+        self.add_param("over_18", &nsfw.to_string())
+    }
+
+    /// Restricts submissions to self (text) posts or link posts.
+    pub fn is_self(&mut self, is_self: bool) -> Result<&mut Self, PSError> {
+        self.require_endpoint("is_self", PSEndpoint::Submission)?;
+        self.add_param("is_self", &is_self.to_string())
+    }
+
+    pub fn distinguished(&mut self, distinguished: Distinguished) -> Result<&mut Self, PSError> {
+        self.require_endpoint_not("distinguished", PSEndpoint::Subreddit)?;
+        self.add_param("distinguished", &distinguished.to_string())
+    }
+
+    pub fn stickied(&mut self, stickied: bool) -> Result<&mut Self, PSError> {
+        self.require_endpoint_not("stickied", PSEndpoint::Subreddit)?;
+        self.add_param("stickied", &stickied.to_string())
+    }
+
+    /// Requests the metadata object alongside data.
+    pub fn metadata(&mut self, metadata: bool) -> Result<&mut Self, PSError> {
+        self.add_param("metadata", &metadata.to_string())
+    }
+
+    /// Counts results per value of `aggregation` in addition to returning data.
+    pub fn aggs(&mut self, aggregation: Aggregation) -> Result<&mut Self, PSError> {
+        self.require_endpoint_not("aggs", PSEndpoint::Subreddit)?;
+        self.add_param("aggs", &aggregation.to_string())
+    }
+
+    /// Bucket width for created_utc aggregations.
+    pub fn frequency(&mut self, frequency: Frequency) -> Result<&mut Self, PSError> {
+        let required = Aggregation::CreatedUTC.to_string();
+        if self.params.get("aggs").and_then(|aggs| aggs.first()) != Some(&required) {
+            return Err(PSError::RequiresParam(
+                "frequency".to_string(),
+                format!("aggs={}", required),
+            ));
+        }
+        self.add_param("frequency", &frequency.to_string())
+    }
+
+    /// Drops aggregation buckets with fewer than `count` results.
+    pub fn min_doc_count(&mut self, count: u32) -> Result<&mut Self, PSError> {
+        if !self.params.contains_key("aggs") {
+            return Err(PSError::RequiresParam(
+                "min_doc_count".to_string(),
+                "aggs".to_string(),
+            ));
+        }
+        self.add_param("min_doc_count", &count.to_string())
+    }
+
     /// Replaces the currently defined subreddit.
     /// This function exists due to my poor API design.
     pub fn replace_sub(&mut self, sub: &str) -> Result<&mut Self, PSError> {
@@ -71,9 +283,9 @@ impl PushshiftBuilder {
         self.subreddit(sub)
     }
 
+    /// Shorthand for `score(Comparison::Gt(thresh))`.
     pub fn score_threshold(&mut self, thresh: u32) -> Result<&mut Self, PSError> {
-        // Admittedly, I should handle >, <, and = but I'm too lazy right now.
-        self.add_param("score", &(String::from(">") + &thresh.to_string()))
+        self.score(Comparison::Gt(thresh.into()))
     }
 
     // Sort is private because PushshiftBuilder always sets sorting now.
@@ -88,11 +300,20 @@ impl PushshiftBuilder {
     }
 
     pub fn subreddit(&mut self, sub: &str) -> Result<&mut Self, PSError> {
-        if VALID_REDDIT.is_match(sub) {
-            Ok(self.add_param("subreddit", sub)?)
-        } else {
-            Err(PSError::InvalidSubreddit(sub.to_string()))
+        let sub = normalize_subreddit(sub)?;
+        self.add_param("subreddit", &sub)
+    }
+
+    /// Restricts results to several subreddits in one request.
+    pub fn subreddits<T: AsRef<str>>(&mut self, subs: &[T]) -> Result<&mut Self, PSError> {
+        if subs.is_empty() {
+            return Err(PSError::EmptyList("subreddit".to_string()));
         }
+        let subs = subs
+            .iter()
+            .map(|sub| normalize_subreddit(sub.as_ref()))
+            .collect::<Result<Vec<_>, _>>()?;
+        self.add_param("subreddit", &subs.join(","))
     }
 
     pub fn size(&mut self, size: u32) -> Result<&mut Self, PSError> {
@@ -103,15 +324,464 @@ impl PushshiftBuilder {
         }
     }
 
+    // Adds values to `param` one at a time and starts a new URL whenever the current one would
+    // grow past MAX_URL_LENGTH. A value that doesn't fit into a URL by itself is an error.
+    fn build_batched_param<T, F>(
+        &mut self,
+        param: &str,
+        values: &[T],
+        mut add: F,
+    ) -> Result<Vec<Url>, PSError>
+    where
+        T: AsRef<str>,
+        F: FnMut(&mut Self, &[&str]) -> Result<(), PSError>,
+    {
+        let mut urls = Vec::new();
+        let mut batch: Vec<&str> = Vec::new();
+        let mut current: Option<Url> = None;
+
+        for value in values.iter().map(AsRef::as_ref) {
+            batch.push(value);
+            self.params.remove(param);
+            add(self, &batch)?;
+            let url = self.build()?;
+
+            if url.as_str().len() <= MAX_URL_LENGTH {
+                current = Some(url);
+                continue;
+            }
+
+            // The previous batch was full so start a new one with the current value.
+            urls.extend(current.take());
+            batch = vec![value];
+            self.params.remove(param);
+            add(self, &batch)?;
+            let url = self.build()?;
+            if url.as_str().len() > MAX_URL_LENGTH {
+                return Err(PSError::UrlTooLong(value.to_string()));
+            }
+            current = Some(url);
+        }
+
+        urls.extend(current);
+        Ok(urls)
+    }
+
+    // Validates each value with `regex` and joins them into a comma separated list.
+    fn validate_list<T, F>(
+        param: &str,
+        values: &[T],
+        regex: &Regex,
+        error: F,
+    ) -> Result<String, PSError>
+    where
+        T: AsRef<str>,
+        F: Fn(String) -> PSError,
+    {
+        if values.is_empty() {
+            return Err(PSError::EmptyList(param.to_string()));
+        }
+
+        values
+            .iter()
+            .map(|value| {
+                let value = value.as_ref();
+                if regex.is_match(value) {
+                    Ok(value)
+                } else {
+                    Err(error(value.to_string()))
+                }
+            })
+            .collect::<Result<Vec<_>, _>>()
+            .map(|values| values.join(","))
+    }
+
+    fn require_endpoint(&self, param: &str, endpoint: PSEndpoint) -> Result<(), PSError> {
+        if self.endpoint == endpoint {
+            Ok(())
+        } else {
+            Err(PSError::WrongEndpoint(param.to_string(), self.endpoint))
+        }
+    }
+
+    fn require_endpoint_not(&self, param: &str, endpoint: PSEndpoint) -> Result<(), PSError> {
+        if self.endpoint != endpoint {
+            Ok(())
+        } else {
+            Err(PSError::WrongEndpoint(param.to_string(), self.endpoint))
+        }
+    }
+
+    // Before/until and after/since are aliases so only one of each pair may be used.
+    fn check_conflict(&self, param: &str, alias: &str) -> Result<(), PSError> {
+        if self.params.contains_key(alias) {
+            Err(PSError::ConflictingParams(
+                param.to_string(),
+                alias.to_string(),
+            ))
+        } else {
+            Ok(())
+        }
+    }
+
+    // Absolute epoch of whichever of `param` or its `alias` is set.
+    fn param_epoch(&self, param: &str, alias: &str) -> Option<u64> {
+        self.params
+            .get(param)
+            .or_else(|| self.params.get(alias))
+            .and_then(|times| times.first())
+            .and_then(|time| time.parse().ok())
+    }
+
+    // Only absolute epochs can be compared; relative times such as 30d are left to the API.
+    fn check_time_range(&self, after: Option<u64>, before: Option<u64>) -> Result<(), PSError> {
+        match (after, before) {
+            (Some(after), Some(before)) if after >= before => {
+                Err(PSError::InvalidTimeRange(after, before))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    fn add_comparison(
+        &mut self,
+        param: &str,
+        comparison: Comparison,
+    ) -> Result<&mut Self, PSError> {
+        if comparison.is_empty() {
+            Err(PSError::EmptyRange(comparison))
+        } else {
+            self.add_params(param, comparison.to_query())
+        }
+    }
+
     fn add_param(&mut self, param: &str, par_options: &str) -> Result<&mut Self, PSError> {
+        self.add_params(param, vec![par_options.to_string()])
+    }
+
+    fn add_params(&mut self, param: &str, par_options: Vec<String>) -> Result<&mut Self, PSError> {
         // Add_param() only allows a parameter to be added once. I decided against allowing
         // replacement in order to be as explicit as possible.
         if self.params.contains_key(param) {
             Err(PSError::AlreadyAdded(param.to_string()))
         } else {
-            self.params
-                .insert(param.to_string(), par_options.to_string());
+            self.params.insert(param.to_string(), par_options);
             Ok(self)
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn query(builder: &mut PushshiftBuilder) -> String {
+        builder.build().unwrap().query().unwrap().to_owned()
+    }
+
+    #[test]
+    fn default_sort_and_before() {
+        let mut builder = PushshiftBuilder::new(PSEndpoint::Comment);
+        builder.subreddit("gaming").unwrap();
+        assert_eq!(
+            builder.build().unwrap().as_str(),
+            "https://api.pushshift.io/reddit/comment/search?\
+             before=4294967295&sort=desc&sort_type=created_utc&subreddit=gaming"
+        );
+    }
+
+    #[test]
+    fn text_author_and_ids() {
+        let mut builder = PushshiftBuilder::new(PSEndpoint::Comment);
+        builder
+            .q("elden ring")
+            .unwrap()
+            .author(&["spez", "some_user-1"])
+            .unwrap()
+            .link_id("t3_abc123")
+            .unwrap()
+            .parent_id("t1_def456")
+            .unwrap()
+            .ids(&["xyz", "t1_uvw"])
+            .unwrap()
+            .fields(&["author", "created_utc"])
+            .unwrap();
+        assert_eq!(
+            query(&mut builder),
+            "author=spez%2Csome_user-1&before=4294967295&fields=author%2Ccreated_utc\
+             &ids=xyz%2Ct1_uvw&link_id=t3_abc123&parent_id=t1_def456&q=elden+ring\
+             &sort=desc&sort_type=created_utc"
+        );
+    }
+
+    #[test]
+    fn submission_flags() {
+        let mut builder = PushshiftBuilder::new(PSEndpoint::Submission);
+        builder
+            .num_comments(Comparison::Gt(10))
+            .unwrap()
+            .over_18(false)
+            .unwrap()
+            .is_self(true)
+            .unwrap()
+            .distinguished(Distinguished::Moderator)
+            .unwrap()
+            .stickied(false)
+            .unwrap()
+            .metadata(true)
+            .unwrap();
+        assert_eq!(
+            query(&mut builder),
+            "before=4294967295&distinguished=moderator&is_self=true&metadata=true\
+             &num_comments=%3E10&over_18=false&sort=desc&sort_type=created_utc&stickied=false"
+        );
+    }
+
+    #[test]
+    fn since_until_replace_default_before() {
+        let mut builder = PushshiftBuilder::new(PSEndpoint::Comment);
+        builder
+            .since(TimeConvenience::UTC(1546300800))
+            .unwrap()
+            .until(TimeConvenience::UTC(1577836800))
+            .unwrap();
+        assert_eq!(
+            query(&mut builder),
+            "since=1546300800&sort=desc&sort_type=created_utc&until=1577836800"
+        );
+    }
+
+    #[test]
+    fn invalid_values() {
+        let mut builder = PushshiftBuilder::new(PSEndpoint::Comment);
+        assert!(matches!(builder.q("  "), Err(PSError::EmptyQuery)));
+        assert!(matches!(
+            builder.author(&["a"]),
+            Err(PSError::InvalidAuthor(_))
+        ));
+        assert!(matches!(
+            builder.author::<&str>(&[]),
+            Err(PSError::EmptyList(_))
+        ));
+        assert!(matches!(
+            builder.link_id("t3_ABC!"),
+            Err(PSError::InvalidId(_))
+        ));
+        assert!(matches!(
+            builder.fields(&["Author"]),
+            Err(PSError::InvalidField(_))
+        ));
+    }
+
+    #[test]
+    fn endpoint_misuse() {
+        let mut comments = PushshiftBuilder::new(PSEndpoint::Comment);
+        assert!(matches!(
+            comments.num_comments(Comparison::Gt(5)),
+            Err(PSError::WrongEndpoint(_, PSEndpoint::Comment))
+        ));
+        assert!(matches!(
+            comments.is_self(true),
+            Err(PSError::WrongEndpoint(_, PSEndpoint::Comment))
+        ));
+
+        let mut submissions = PushshiftBuilder::new(PSEndpoint::Submission);
+        assert!(matches!(
+            submissions.parent_id("t1_abc"),
+            Err(PSError::WrongEndpoint(_, PSEndpoint::Submission))
+        ));
+    }
+
+    #[test]
+    fn conflicting_times() {
+        let mut builder = PushshiftBuilder::new(PSEndpoint::Comment);
+        builder.after(TimeConvenience::UTC(100)).unwrap();
+        assert!(matches!(
+            builder.since(TimeConvenience::Days(30)),
+            Err(PSError::ConflictingParams(..))
+        ));
+        assert!(matches!(
+            builder.before(TimeConvenience::UTC(50)),
+            Err(PSError::InvalidTimeRange(100, 50))
+        ));
+        assert!(matches!(
+            builder.after(TimeConvenience::UTC(10)),
+            Err(PSError::AlreadyAdded(_))
+        ));
+    }
+
+    #[test]
+    fn score_comparisons() {
+        let mut builder = PushshiftBuilder::new(PSEndpoint::Comment);
+        builder.score(Comparison::Lt(-5)).unwrap();
+        assert_eq!(
+            query(&mut builder),
+            "before=4294967295&score=%3C-5&sort=desc&sort_type=created_utc"
+        );
+
+        let mut builder = PushshiftBuilder::new(PSEndpoint::Comment);
+        builder.score(Comparison::Eq(1)).unwrap();
+        assert_eq!(
+            query(&mut builder),
+            "before=4294967295&score=1&sort=desc&sort_type=created_utc"
+        );
+
+        let mut builder = PushshiftBuilder::new(PSEndpoint::Submission);
+        builder
+            .score(Comparison::Range(10, 20))
+            .unwrap()
+            .num_comments(Comparison::Range(0, 0))
+            .unwrap();
+        assert_eq!(
+            query(&mut builder),
+            "before=4294967295&num_comments=%3E-1&num_comments=%3C1\
+             &score=%3E9&score=%3C21&sort=desc&sort_type=created_utc"
+        );
+
+        assert!(matches!(
+            builder.score(Comparison::Range(5, 1)),
+            Err(PSError::EmptyRange(_))
+        ));
+    }
+
+    #[test]
+    fn created_utc_comparisons() {
+        let mut builder = PushshiftBuilder::new(PSEndpoint::Comment);
+        builder
+            .created_utc(Comparison::Range(1546300800, 1577836799))
+            .unwrap();
+        assert_eq!(
+            query(&mut builder),
+            "after=1546300799&before=1577836800&sort=desc&sort_type=created_utc"
+        );
+    }
+
+    #[test]
+    fn batched_subreddits() {
+        let mut builder = PushshiftBuilder::new(PSEndpoint::Comment);
+        let urls = builder
+            .build_batched(&["gaming", "pcgaming", "Games"])
+            .unwrap();
+        assert_eq!(urls.len(), 1);
+        assert_eq!(
+            urls[0].query().unwrap(),
+            "before=4294967295&sort=desc&sort_type=created_utc&subreddit=gaming%2Cpcgaming%2Cgames"
+        );
+
+        // 200 subreddits of 21 characters can't fit into a single URL.
+        let subs: Vec<_> = (0..200)
+            .map(|i| format!("subreddit_number_{:04}", i))
+            .collect();
+        let urls = builder.build_batched(&subs).unwrap();
+        assert!(urls.len() > 1);
+        assert!(urls.iter().all(|url| url.as_str().len() <= MAX_URL_LENGTH));
+
+        let batched: Vec<String> = urls
+            .iter()
+            .flat_map(|url| {
+                url.query_pairs()
+                    .filter(|(param, _)| param == "subreddit")
+                    .flat_map(|(_, subs)| subs.split(',').map(str::to_owned).collect::<Vec<_>>())
+                    .collect::<Vec<_>>()
+            })
+            .collect();
+        assert_eq!(batched, subs);
+    }
+
+    #[test]
+    fn batched_authors() {
+        let mut builder = PushshiftBuilder::new(PSEndpoint::Comment);
+        let urls = builder
+            .build_batched_authors(&["spez", "kn0thing"])
+            .unwrap();
+        assert_eq!(
+            urls[0].query().unwrap(),
+            "author=spez%2Ckn0thing&before=4294967295&sort=desc&sort_type=created_utc"
+        );
+    }
+
+    #[test]
+    fn fields_from_output_mode() {
+        use crate::scraperclient::nodestructs::OutputMode;
+
+        let mut builder = PushshiftBuilder::new(PSEndpoint::Comment);
+        builder.fields(OutputMode::Node.fields()).unwrap();
+        assert_eq!(
+            query(&mut builder),
+            "before=4294967295&fields=author%2Ccreated_utc%2Cpermalink%2Csubreddit\
+             &sort=desc&sort_type=created_utc"
+        );
+    }
+
+    #[test]
+    fn aggregations() {
+        let mut builder = PushshiftBuilder::new(PSEndpoint::Comment);
+        assert!(matches!(
+            builder.min_doc_count(2),
+            Err(PSError::RequiresParam(..))
+        ));
+        builder
+            .author(&["spez"])
+            .unwrap()
+            .aggs(Aggregation::Subreddit)
+            .unwrap()
+            .min_doc_count(2)
+            .unwrap()
+            .size(0)
+            .unwrap();
+        assert!(matches!(
+            builder.frequency(Frequency::Day),
+            Err(PSError::RequiresParam(..))
+        ));
+        assert_eq!(
+            query(&mut builder),
+            "aggs=subreddit&author=spez&before=4294967295&min_doc_count=2&size=0\
+             &sort=desc&sort_type=created_utc"
+        );
+
+        let mut builder = PushshiftBuilder::new(PSEndpoint::Submission);
+        builder
+            .aggs(Aggregation::CreatedUTC)
+            .unwrap()
+            .frequency(Frequency::Week)
+            .unwrap();
+        assert_eq!(
+            query(&mut builder),
+            "aggs=created_utc&before=4294967295&frequency=week&sort=desc&sort_type=created_utc"
+        );
+    }
+
+    #[test]
+    fn ascending_windows() {
+        let mut builder = PushshiftBuilder::new(PSEndpoint::Comment);
+        builder
+            .order(Sort::Asc)
+            .unwrap()
+            .window(1546300800, 1577836800)
+            .unwrap();
+        assert_eq!(
+            query(&mut builder),
+            "after=1546300799&before=1577836800&sort=asc&sort_type=created_utc"
+        );
+        assert!(matches!(
+            builder.order(Sort::Desc),
+            Err(PSError::AlreadyAdded(_))
+        ));
+
+        let mut builder = PushshiftBuilder::new(PSEndpoint::Comment);
+        builder
+            .order(Sort::Asc)
+            .unwrap()
+            .subreddit("gaming")
+            .unwrap();
+        assert_eq!(
+            query(&mut builder),
+            "after=0&sort=asc&sort_type=created_utc&subreddit=gaming"
+        );
+
+        assert!(matches!(
+            PushshiftBuilder::new(PSEndpoint::Comment).window(10, 10),
+            Err(PSError::InvalidTimeRange(10, 10))
+        ));
+    }
+}
@@ -1,10 +1,12 @@
 use std::fmt::{Display, Formatter, Result};
 
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Hash)]
 pub enum Sort {
     Desc,
     Asc,
 }
 
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Hash)]
 pub enum Parameter {
     CreatedUTC,
     Score,
@@ -27,3 +29,67 @@ impl Display for Parameter {
         }
     }
 }
+
+/// Values accepted by the `distinguished` parameter.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Hash)]
+pub enum Distinguished {
+    Admin,
+    Moderator,
+    Special,
+}
+
+impl Display for Distinguished {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        match self {
+            Distinguished::Admin => write!(f, "admin"),
+            Distinguished::Moderator => write!(f, "moderator"),
+            Distinguished::Special => write!(f, "special"),
+        }
+    }
+}
+
+/// Fields that can be aggregated with the `aggs` parameter.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Hash)]
+pub enum Aggregation {
+    Author,
+    CreatedUTC,
+    LinkId,
+    Subreddit,
+}
+
+/// Bucket widths for `created_utc` aggregations.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Hash)]
+pub enum Frequency {
+    Second,
+    Minute,
+    Hour,
+    Day,
+    Week,
+    Month,
+    Year,
+}
+
+impl Display for Aggregation {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        match self {
+            Aggregation::Author => write!(f, "author"),
+            Aggregation::CreatedUTC => write!(f, "created_utc"),
+            Aggregation::LinkId => write!(f, "link_id"),
+            Aggregation::Subreddit => write!(f, "subreddit"),
+        }
+    }
+}
+
+impl Display for Frequency {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        match self {
+            Frequency::Second => write!(f, "second"),
+            Frequency::Minute => write!(f, "minute"),
+            Frequency::Hour => write!(f, "hour"),
+            Frequency::Day => write!(f, "day"),
+            Frequency::Week => write!(f, "week"),
+            Frequency::Month => write!(f, "month"),
+            Frequency::Year => write!(f, "year"),
+        }
+    }
+}
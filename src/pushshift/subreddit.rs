@@ -0,0 +1,62 @@
+use regex::Regex;
+use std::sync::LazyLock;
+
+use super::pserror::PSError;
+
+// Subreddit names are letters, digits, and underscores that don't start with an underscore.
+// New subreddits need at least 3 characters but a few older ones such as r/de only have 2.
+static VALID_SUBREDDIT: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"^[A-Za-z0-9][A-Za-z0-9_]{1,20}$").unwrap());
+
+/// Strips an `r/` or `/r/` prefix and trailing slash from a subreddit's name and lowercases it.
+/// Fails if what remains isn't a valid name.
+pub fn normalize_subreddit(name: &str) -> Result<String, PSError> {
+    let trimmed = name.trim().trim_end_matches('/');
+    let trimmed = trimmed
+        .strip_prefix("/r/")
+        .or_else(|| trimmed.strip_prefix("r/"))
+        .unwrap_or(trimmed);
+
+    if VALID_SUBREDDIT.is_match(trimmed) {
+        Ok(trimmed.to_lowercase())
+    } else {
+        Err(PSError::InvalidSubreddit(name.to_string()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn normalizes_names() {
+        assert_eq!(normalize_subreddit("gaming").unwrap(), "gaming");
+        assert_eq!(normalize_subreddit("r/Games").unwrap(), "games");
+        assert_eq!(normalize_subreddit(" /r/PCGaming/ ").unwrap(), "pcgaming");
+        assert_eq!(normalize_subreddit("de").unwrap(), "de");
+        assert_eq!(
+            normalize_subreddit("a_very_long_name_of21").unwrap(),
+            "a_very_long_name_of21"
+        );
+    }
+
+    #[test]
+    fn rejects_invalid_names() {
+        for name in [
+            "r/gaming?x=1",
+            "pc-gaming",
+            "_gaming",
+            "g",
+            "",
+            "r/",
+            "a_very_long_name_of_22",
+            "gaming,games",
+        ] {
+            assert!(
+                matches!(normalize_subreddit(name), Err(PSError::InvalidSubreddit(_))),
+                "{}",
+                name
+            );
+        }
+    }
+}
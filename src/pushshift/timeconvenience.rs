@@ -1,6 +1,16 @@
-use std::fmt::{Display, Formatter, Result};
+use std::{
+    fmt::{Display, Formatter, Result},
+    str::FromStr,
+    time::{SystemTime, UNIX_EPOCH},
+};
 
-#[derive(Debug, Copy, Clone, Hash)]
+use serde::{Deserialize, Serialize};
+
+use super::pserror::PSError;
+
+/// Times are serialized in their Display form, i.e. `30d` or an epoch.
+#[derive(Debug, Copy, Clone, Hash, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(into = "String", try_from = "String")]
 pub enum TimeConvenience {
     Seconds(u32),
     Minutes(u32),
@@ -11,6 +21,49 @@ pub enum TimeConvenience {
     UTC(u64),
 }
 
+impl TimeConvenience {
+    /// Returns the epoch if the time is absolute rather than relative to now.
+    pub fn epoch(&self) -> Option<u64> {
+        match self {
+            TimeConvenience::UTC(time) => Some(*time),
+            _ => None,
+        }
+    }
+
+    /// Length of a relative time in seconds. Years are 365 days.
+    pub fn seconds(&self) -> Option<u64> {
+        use TimeConvenience::*;
+        match *self {
+            Seconds(time) => Some(time.into()),
+            Minutes(time) => Some(u64::from(time) * 60),
+            Hours(time) => Some(u64::from(time) * 60 * 60),
+            Days(time) => Some(u64::from(time) * 60 * 60 * 24),
+            Weeks(time) => Some(u64::from(time) * 60 * 60 * 24 * 7),
+            Years(time) => Some(u64::from(time) * 60 * 60 * 24 * 365),
+            UTC(_) => None,
+        }
+    }
+
+    /// Converts relative times to an absolute epoch counted back from `anchor`.
+    /// Anchoring at the start of a scrape keeps the window fixed across requests and resumed runs
+    /// whereas the API resolves relative times against its own clock for every request.
+    pub fn to_epoch(&self, anchor: u64) -> u64 {
+        match (self.epoch(), self.seconds()) {
+            (Some(epoch), _) => epoch,
+            (_, Some(seconds)) => anchor.saturating_sub(seconds),
+            _ => unreachable!("TimeConvenience is either absolute or relative"),
+        }
+    }
+
+    /// Current epoch for use as an anchor.
+    pub fn now() -> u64 {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|now| now.as_secs())
+            .unwrap_or(0)
+    }
+}
+
 impl Display for TimeConvenience {
     fn fmt(&self, f: &mut Formatter<'_>) -> Result {
         use TimeConvenience::*;
@@ -25,3 +78,175 @@ impl Display for TimeConvenience {
         }
     }
 }
+
+/// Parses relative times such as `30d` or `2w`, epochs, and ISO-8601 dates (`2019-01-01`) or
+/// datetimes (`2019-01-01T12:30:00Z`, `2019-01-01 12:30:00+02:00`). Datetimes without an offset
+/// are treated as UTC.
+impl FromStr for TimeConvenience {
+    type Err = PSError;
+
+    fn from_str(time: &str) -> std::result::Result<Self, Self::Err> {
+        use TimeConvenience::*;
+        let time = time.trim();
+        let invalid = || PSError::InvalidTime(time.to_string());
+
+        if let Ok(epoch) = time.parse::<u64>() {
+            return Ok(UTC(epoch));
+        }
+        if time.contains('-') {
+            return parse_iso8601(time).map(UTC).ok_or_else(invalid);
+        }
+
+        // Split at the last character rather than byte so that non-ASCII input can't panic.
+        let (split, _) = time.char_indices().last().ok_or_else(invalid)?;
+        let (amount, unit) = time.split_at(split);
+        let amount: u32 = amount.parse().map_err(|_| invalid())?;
+        match unit {
+            "s" => Ok(Seconds(amount)),
+            "m" => Ok(Minutes(amount)),
+            "h" => Ok(Hours(amount)),
+            "d" => Ok(Days(amount)),
+            "w" => Ok(Weeks(amount)),
+            "y" => Ok(Years(amount)),
+            _ => Err(invalid()),
+        }
+    }
+}
+
+impl From<TimeConvenience> for String {
+    fn from(time: TimeConvenience) -> Self {
+        time.to_string()
+    }
+}
+
+impl TryFrom<String> for TimeConvenience {
+    type Error = PSError;
+
+    fn try_from(time: String) -> std::result::Result<Self, Self::Error> {
+        time.parse()
+    }
+}
+
+// Epoch of an ISO-8601 date or datetime. Returns None for anything before 1970.
+fn parse_iso8601(time: &str) -> Option<u64> {
+    let (date, clock) = match time.split_once(['T', ' ']) {
+        Some((date, clock)) => (date, Some(clock)),
+        None => (time, None),
+    };
+
+    let mut date = date.splitn(3, '-').map(str::parse::<u32>);
+    let (year, month, day) = (date.next()?.ok()?, date.next()?.ok()?, date.next()?.ok()?);
+    if !(1..=12).contains(&month) || day == 0 || day > days_in_month(year, month) {
+        return None;
+    }
+    let mut seconds = days_from_civil(year, month, day)? * 86400;
+
+    if let Some(clock) = clock {
+        // Split off the offset which is either Z or a signed HH:MM.
+        let (clock, offset) = if let Some(clock) = clock.strip_suffix('Z') {
+            (clock, 0)
+        } else if let Some(position) = clock.rfind(['+', '-']) {
+            let (clock, offset) = clock.split_at(position);
+            let sign = if offset.starts_with('-') { -1 } else { 1 };
+            let (hours, minutes) = offset[1..].split_once(':').unwrap_or((&offset[1..], "0"));
+            let offset = hours.parse::<i64>().ok()? * 3600 + minutes.parse::<i64>().ok()? * 60;
+            (clock, sign * offset)
+        } else {
+            (clock, 0)
+        };
+
+        let mut clock = clock.splitn(3, ':');
+        let hours: u64 = clock.next()?.parse().ok()?;
+        let minutes: u64 = clock.next().unwrap_or("0").parse().ok()?;
+        // Fractional seconds are dropped.
+        let secs: u64 = clock
+            .next()
+            .unwrap_or("0")
+            .split('.')
+            .next()?
+            .parse()
+            .ok()?;
+        if hours > 23 || minutes > 59 || secs > 60 {
+            return None;
+        }
+
+        seconds += hours * 3600 + minutes * 60 + secs;
+        seconds = u64::try_from(i64::try_from(seconds).ok()? - offset).ok()?;
+    }
+
+    Some(seconds)
+}
+
+fn days_in_month(year: u32, month: u32) -> u32 {
+    match month {
+        2 if year.is_multiple_of(4) && (!year.is_multiple_of(100) || year.is_multiple_of(400)) => {
+            29
+        }
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+// Days since 1970-01-01 using Howard Hinnant's days_from_civil algorithm.
+fn days_from_civil(year: u32, month: u32, day: u32) -> Option<u64> {
+    let year = i64::from(year) - i64::from(month <= 2);
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let month = i64::from(month);
+    let day_of_year = (153 * (month + if month > 2 { -3 } else { 9 }) + 2) / 5 + i64::from(day) - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    u64::try_from(era * 146097 + day_of_era - 719468).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_relative_and_epochs() {
+        assert_eq!(
+            "30d".parse::<TimeConvenience>().unwrap(),
+            TimeConvenience::Days(30)
+        );
+        assert_eq!(
+            "2w".parse::<TimeConvenience>().unwrap(),
+            TimeConvenience::Weeks(2)
+        );
+        assert_eq!(
+            "1546300800".parse::<TimeConvenience>().unwrap(),
+            TimeConvenience::UTC(1546300800)
+        );
+        assert!("30x".parse::<TimeConvenience>().is_err());
+        assert!("".parse::<TimeConvenience>().is_err());
+        assert!(matches!(
+            "30é".parse::<TimeConvenience>(),
+            Err(PSError::InvalidTime(_))
+        ));
+        assert!("日".parse::<TimeConvenience>().is_err());
+    }
+
+    #[test]
+    fn parse_iso8601() {
+        let parse = |time: &str| time.parse::<TimeConvenience>().ok();
+        assert_eq!(parse("1970-01-01"), Some(TimeConvenience::UTC(0)));
+        assert_eq!(parse("2019-01-01"), Some(TimeConvenience::UTC(1546300800)));
+        assert_eq!(parse("2020-02-29"), Some(TimeConvenience::UTC(1582934400)));
+        assert_eq!(
+            parse("2019-12-31T23:59:59Z"),
+            Some(TimeConvenience::UTC(1577836799))
+        );
+        assert_eq!(
+            parse("2019-01-01 02:00:00+02:00"),
+            Some(TimeConvenience::UTC(1546300800))
+        );
+        assert_eq!(parse("2019-02-29"), None);
+        assert_eq!(parse("2019-01-01T24:00:00"), None);
+    }
+
+    #[test]
+    fn anchored_epochs() {
+        assert_eq!(TimeConvenience::Days(1).to_epoch(100000), 100000 - 86400);
+        assert_eq!(TimeConvenience::UTC(5).to_epoch(100000), 5);
+    }
+}
@@ -1,2 +1,10 @@
+pub mod bots;
+pub mod cache;
 pub mod client;
+pub mod events;
+pub mod hashing;
+pub mod nodefilter;
 pub mod nodestructs;
+pub mod quota;
+pub mod stop;
+pub mod stratified;
@@ -0,0 +1,363 @@
+use log::info;
+use regex::{Regex, RegexBuilder};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{hash_map::DefaultHasher, HashMap, HashSet},
+    fmt::{Display, Formatter},
+    fs,
+    hash::{Hash, Hasher},
+    path::Path,
+};
+
+use super::{hashing::NameHasher, nodefilter::NodeFilter, nodestructs::RawNode};
+use crate::pushshift::PSError;
+
+// Placeholders for deleted accounts and Reddit's own moderation bot are never people.
+static ALWAYS_BLOCKED: [&str; 2] = ["[deleted]", "AutoModerator"];
+// Fewer items than this say too little about how fast an account posts.
+static MIN_RATE_ITEMS: u32 = 10;
+
+/// Why an account was dropped as a bot.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum BotReason {
+    Blocklist,
+    Pattern,
+    Rate,
+    Repetition,
+    Distinguished,
+}
+
+impl Display for BotReason {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let reason = match self {
+            BotReason::Blocklist => "blocklist",
+            BotReason::Pattern => "pattern",
+            BotReason::Rate => "rate",
+            BotReason::Repetition => "repetition",
+            BotReason::Distinguished => "distinguished",
+        };
+        write!(f, "{}", reason)
+    }
+}
+
+/// A dropped account as written to the audit file.
+#[derive(Clone, Debug, Eq, PartialEq, Hash, Serialize, Deserialize)]
+pub struct BotRecord {
+    pub author: String,
+    pub reason: BotReason,
+    /// The matching pattern or the measurement that crossed its threshold.
+    pub detail: String,
+}
+
+// What an account posted so far during the scrape.
+#[derive(Clone, Debug, Default)]
+struct Activity {
+    items: u32,
+    first: u64,
+    last: u64,
+    // Number of items per hash of their bodies
+    bodies: HashMap<u64, u32>,
+}
+
+/// Decides which accounts are bots by their names and by how they post.
+///
+/// Names are checked against blocklists and case insensitive patterns. Behavioural heuristics
+/// look at the RawNodes observed so far, so an account may only be flagged after some of its
+/// items were accepted. Callers should drop those items using take_retracted().
+#[derive(Clone, Debug)]
+pub struct BotFilter {
+    activity: HashMap<String, Activity>,
+    // Lowercase names
+    blocklist: HashSet<String>,
+    distinguished: bool,
+    flagged: HashMap<String, BotRecord>,
+    max_rate: Option<f64>,
+    max_repeats: Option<u32>,
+    patterns: Vec<Regex>,
+    // Accounts flagged by their behaviour since the last take_retracted()
+    retracted: Vec<String>,
+}
+
+impl Default for BotFilter {
+    fn default() -> Self {
+        BotFilter {
+            activity: HashMap::new(),
+            blocklist: ALWAYS_BLOCKED
+                .iter()
+                .map(|name| name.to_lowercase())
+                .collect(),
+            distinguished: false,
+            flagged: HashMap::new(),
+            max_rate: None,
+            max_repeats: None,
+            patterns: Vec::new(),
+            retracted: Vec::new(),
+        }
+    }
+}
+
+impl BotFilter {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Blocks an account. The name may be prefixed by u/ or /u/.
+    pub fn block(&mut self, name: &str) -> &mut Self {
+        let name = name.trim();
+        let name = name
+            .strip_prefix("/u/")
+            .or_else(|| name.strip_prefix("u/"))
+            .unwrap_or(name);
+        if !name.is_empty() {
+            self.blocklist.insert(name.to_lowercase());
+        }
+        self
+    }
+
+    /// Blocks every account in a file with one name per line. Empty lines and lines starting
+    /// with # are ignored.
+    pub fn read_blocklist<P: AsRef<Path>>(&mut self, path: P) -> Result<&mut Self, PSError> {
+        let contents = fs::read_to_string(&path).map_err(PSError::reading(&path))?;
+        for line in contents.lines() {
+            if !line.trim_start().starts_with('#') {
+                self.block(line);
+            }
+        }
+        Ok(self)
+    }
+
+    /// Blocks accounts whose names match a case insensitive regular expression, e.g. `bot$`.
+    pub fn pattern(&mut self, pattern: &str) -> Result<&mut Self, PSError> {
+        let regex = RegexBuilder::new(pattern)
+            .case_insensitive(true)
+            .build()
+            .map_err(|_| PSError::InvalidPattern(pattern.to_string()))?;
+        self.patterns.push(regex);
+        Ok(self)
+    }
+
+    /// Flags accounts that post more than `per_hour` items per hour once at least
+    /// MIN_RATE_ITEMS of their items were seen.
+    pub fn max_rate(&mut self, per_hour: f64) -> &mut Self {
+        self.max_rate = Some(per_hour);
+        self
+    }
+
+    /// Flags accounts that post the same body `repeats` times. Requires the body field.
+    pub fn max_repeats(&mut self, repeats: u32) -> &mut Self {
+        self.max_repeats = Some(repeats.max(2));
+        self
+    }
+
+    /// Flags accounts with distinguished (moderator or admin) items. Requires the distinguished
+    /// field.
+    pub fn distinguished(&mut self, distinguished: bool) -> &mut Self {
+        self.distinguished = distinguished;
+        self
+    }
+
+    // Checks the name alone.
+    fn blocked_name(&self, author: &str) -> Option<BotRecord> {
+        let record = |reason, detail: &str| {
+            Some(BotRecord {
+                author: author.to_string(),
+                reason,
+                detail: detail.to_string(),
+            })
+        };
+
+        if self.blocklist.contains(&author.to_lowercase()) {
+            return record(BotReason::Blocklist, "");
+        }
+        self.patterns
+            .iter()
+            .find(|pattern| pattern.is_match(author))
+            .and_then(|pattern| record(BotReason::Pattern, pattern.as_str()))
+    }
+
+    /// Whether the account was flagged or is blocked by name.
+    pub fn is_bot(&self, author: &str) -> bool {
+        self.flagged.contains_key(author) || self.blocked_name(author).is_some()
+    }
+
+    /// Records an item and returns whether its author still looks like a person.
+    pub fn observe(&mut self, raw: &RawNode) -> bool {
+        let author = raw.author();
+        if self.flagged.contains_key(author) {
+            return false;
+        }
+        if let Some(record) = self.blocked_name(author) {
+            self.flag(record);
+            return false;
+        }
+
+        let activity = self.activity.entry(author.to_string()).or_default();
+        activity.first = if activity.items == 0 {
+            raw.created_utc
+        } else {
+            activity.first.min(raw.created_utc)
+        };
+        activity.last = activity.last.max(raw.created_utc);
+        activity.items += 1;
+        let repeats = raw
+            .body()
+            .filter(|body| !matches!(body.trim(), "" | "[deleted]" | "[removed]"))
+            .map(|body| {
+                let mut hasher = DefaultHasher::new();
+                body.hash(&mut hasher);
+                let count = activity.bodies.entry(hasher.finish()).or_insert(0);
+                *count += 1;
+                *count
+            })
+            .unwrap_or(0);
+        // At least one second so that a burst within the same second has a finite rate.
+        let rate =
+            f64::from(activity.items) * 3600.0 / (activity.last - activity.first).max(1) as f64;
+        let items = activity.items;
+
+        let (reason, detail) = match raw.distinguished() {
+            Some(distinguished) if self.distinguished => {
+                (BotReason::Distinguished, distinguished.to_string())
+            }
+            _ if self.max_repeats.is_some_and(|max| repeats >= max) => (
+                BotReason::Repetition,
+                format!("{} identical bodies", repeats),
+            ),
+            _ if items >= MIN_RATE_ITEMS && self.max_rate.is_some_and(|max| rate > max) => {
+                (BotReason::Rate, format!("{:.1} items per hour", rate))
+            }
+            _ => return true,
+        };
+        let author = author.to_string();
+        if self
+            .activity
+            .get(&author)
+            .is_some_and(|activity| activity.items > 1)
+        {
+            self.retracted.push(author.clone());
+        }
+        self.flag(BotRecord {
+            author,
+            reason,
+            detail,
+        });
+        false
+    }
+
+    /// Accounts flagged by their behaviour since the last call whose earlier items were
+    /// accepted.
+    pub fn take_retracted(&mut self) -> Vec<String> {
+        std::mem::take(&mut self.retracted)
+    }
+
+    fn flag(&mut self, record: BotRecord) {
+        info!(
+            "Dropping {} as a bot ({}): {}",
+            record.author, record.reason, record.detail
+        );
+        self.activity.remove(&record.author);
+        self.flagged.insert(record.author.clone(), record);
+    }
+
+    /// Every flagged account sorted by name.
+    pub fn audit(&self) -> Vec<BotRecord> {
+        let mut records: Vec<_> = self.flagged.values().cloned().collect();
+        records.sort_by(|record, other| record.author.cmp(&other.author));
+        records
+    }
+
+    /// Hashes the names of flagged accounts like those of the nodes. is_bot() only knows the
+    /// hashed names afterwards.
+    pub fn hash_names(&mut self, hasher: &NameHasher) {
+        self.flagged = self
+            .flagged
+            .drain()
+            .map(|(author, mut record)| {
+                record.author = hasher.hash(author.as_bytes());
+                (record.author.clone(), record)
+            })
+            .collect();
+    }
+}
+
+impl NodeFilter for BotFilter {
+    fn name(&self) -> &'static str {
+        "bots"
+    }
+
+    fn accepts(&mut self, raw: &RawNode) -> bool {
+        self.observe(raw)
+    }
+
+    fn fields(&self) -> Vec<&'static str> {
+        let mut fields = Vec::new();
+        if self.max_repeats.is_some() {
+            fields.push("body");
+        }
+        if self.distinguished {
+            fields.push("distinguished");
+        }
+        fields
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn raw(author: &str, created_utc: u64, body: &str) -> RawNode {
+        serde_json::from_value(serde_json::json!({
+            "author": author,
+            "body": body,
+            "created_utc": created_utc,
+            "permalink": "/r/gaming/comments/abc/title/def/",
+            "subreddit": "gaming",
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn flags_names_and_behaviour() {
+        let mut filter = BotFilter::new();
+        filter
+            .block("u/SomeHelper")
+            .pattern("bot$")
+            .unwrap()
+            .max_repeats(3)
+            .max_rate(60.0);
+
+        assert!(!filter.observe(&raw("automoderator", 0, "")));
+        assert!(!filter.observe(&raw("somehelper", 0, "")));
+        assert!(!filter.observe(&raw("RemindMeBot", 0, "")));
+        assert!(filter.observe(&raw("abbot_fan", 0, "")));
+
+        for time in 0..2 {
+            assert!(filter.observe(&raw("parrot", time * 3600, "same")));
+        }
+        assert!(!filter.observe(&raw("parrot", 7200, "same")));
+        assert_eq!(filter.take_retracted(), vec!["parrot"]);
+
+        for time in 0..MIN_RATE_ITEMS {
+            filter.observe(&raw("spammer", u64::from(time), &time.to_string()));
+        }
+        assert!(filter.is_bot("spammer"));
+
+        let reasons: Vec<_> = filter
+            .audit()
+            .into_iter()
+            .map(|record| (record.author, record.reason))
+            .collect();
+        assert_eq!(
+            reasons,
+            vec![
+                ("RemindMeBot".to_string(), BotReason::Pattern),
+                ("automoderator".to_string(), BotReason::Blocklist),
+                ("parrot".to_string(), BotReason::Repetition),
+                ("somehelper".to_string(), BotReason::Blocklist),
+                ("spammer".to_string(), BotReason::Rate),
+            ]
+        );
+        assert!(filter.pattern("(").is_err());
+    }
+}
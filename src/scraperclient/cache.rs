@@ -0,0 +1,166 @@
+use reqwest::Url;
+use ring::digest::{digest, SHA256};
+use serde::{Deserialize, Serialize};
+use std::{
+    fmt::{Display, Formatter},
+    fs,
+    io::ErrorKind,
+    path::PathBuf,
+    str::FromStr,
+};
+
+use crate::pushshift::PSError;
+
+/// How a ResponseCache treats requests.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum CacheMode {
+    /// Serves recorded responses and records the rest.
+    #[default]
+    Record,
+    /// Only serves recorded responses and never touches the network.
+    Replay,
+    /// Neither reads nor writes the cache.
+    PassThrough,
+}
+
+impl Display for CacheMode {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let mode = match self {
+            CacheMode::Record => "record",
+            CacheMode::Replay => "replay",
+            CacheMode::PassThrough => "pass-through",
+        };
+        write!(f, "{}", mode)
+    }
+}
+
+impl FromStr for CacheMode {
+    type Err = PSError;
+
+    fn from_str(mode: &str) -> Result<Self, Self::Err> {
+        match mode.trim().to_lowercase().as_str() {
+            "record" => Ok(CacheMode::Record),
+            "replay" => Ok(CacheMode::Replay),
+            "pass-through" | "passthrough" => Ok(CacheMode::PassThrough),
+            _ => Err(PSError::InvalidCacheMode(mode.to_string())),
+        }
+    }
+}
+
+/// Successful responses stored on disk by the SHA256 of their normalised request URL so that
+/// scrapes can be repeated offline with the same results.
+///
+/// Each response body is stored verbatim at `{directory}/{first two digits}/{digest}.json`.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ResponseCache {
+    pub directory: PathBuf,
+    #[serde(default)]
+    pub mode: CacheMode,
+}
+
+impl ResponseCache {
+    pub fn new<P: Into<PathBuf>>(directory: P, mode: CacheMode) -> Self {
+        ResponseCache {
+            directory: directory.into(),
+            mode,
+        }
+    }
+
+    /// Whether requests never reach the network.
+    pub fn is_offline(&self) -> bool {
+        self.mode == CacheMode::Replay
+    }
+
+    /// The URL with its query pairs sorted so that equivalent queries share a response.
+    pub fn normalize(url: &Url) -> String {
+        let mut pairs: Vec<_> = url.query_pairs().into_owned().collect();
+        pairs.sort();
+        let mut normalized = url.clone();
+        normalized.set_fragment(None);
+        normalized.set_query(None);
+        if !pairs.is_empty() {
+            normalized.query_pairs_mut().extend_pairs(pairs);
+        }
+        normalized.into()
+    }
+
+    /// Where the response to `url` is stored.
+    pub fn path(&self, url: &Url) -> PathBuf {
+        let key = hex::encode(digest(&SHA256, ResponseCache::normalize(url).as_bytes()));
+        self.directory.join(&key[..2]).join(format!("{}.json", key))
+    }
+
+    /// The recorded response to `url`. If there is none, replaying fails while recording and
+    /// passing through return None.
+    pub fn get(&self, url: &Url) -> Result<Option<Vec<u8>>, PSError> {
+        if self.mode == CacheMode::PassThrough {
+            return Ok(None);
+        }
+        let path = self.path(url);
+        match fs::read(&path) {
+            Ok(body) => Ok(Some(body)),
+            Err(error) if error.kind() == ErrorKind::NotFound => match self.mode {
+                CacheMode::Replay => Err(PSError::NotCached(url.to_string())),
+                _ => Ok(None),
+            },
+            Err(error) => Err(PSError::reading(&path)(error)),
+        }
+    }
+
+    /// Stores the response to `url` when recording.
+    pub fn put(&self, url: &Url, body: &[u8]) -> Result<(), PSError> {
+        if self.mode != CacheMode::Record {
+            return Ok(());
+        }
+        let path = self.path(url);
+        // Writes to a temporary file first so that an interrupted scrape can't leave a
+        // truncated response behind.
+        let partial = path.with_extension("partial");
+        let store = || -> Result<(), std::io::Error> {
+            if let Some(parent) = path.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            fs::write(&partial, body)?;
+            fs::rename(&partial, &path)
+        };
+        store().map_err(PSError::writing(&path))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testdir::TestDir;
+
+    #[test]
+    fn records_and_replays() {
+        let directory = TestDir::new("cache");
+        let url = Url::parse("https://api.pushshift.io/reddit/comment/search?size=5&subreddit=a")
+            .unwrap();
+        let reordered =
+            Url::parse("https://api.pushshift.io/reddit/comment/search?subreddit=a&size=5#top")
+                .unwrap();
+        let other =
+            Url::parse("https://api.pushshift.io/reddit/comment/search?subreddit=b").unwrap();
+
+        let record = ResponseCache::new(directory.path(), CacheMode::Record);
+        assert_eq!(record.get(&url).unwrap(), None);
+        record.put(&url, b"{\"data\": []}").unwrap();
+        assert_eq!(record.path(&url), record.path(&reordered));
+
+        let replay = ResponseCache::new(directory.path(), CacheMode::Replay);
+        assert_eq!(
+            replay.get(&reordered).unwrap().as_deref(),
+            Some(&b"{\"data\": []}"[..])
+        );
+        assert!(matches!(replay.get(&other), Err(PSError::NotCached(_))));
+        replay.put(&other, b"{}").unwrap();
+        assert!(!replay.path(&other).exists());
+
+        let pass = ResponseCache::new(directory.path(), CacheMode::PassThrough);
+        assert_eq!(pass.get(&url).unwrap(), None);
+        assert_eq!("passthrough".parse::<CacheMode>().unwrap(), pass.mode);
+    }
+}
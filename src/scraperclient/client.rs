@@ -1,28 +1,119 @@
-use futures::TryFutureExt;
-use log::{debug, error, info};
+use futures::stream::{self, BoxStream, StreamExt};
+use log::{debug, error, info, warn};
 use reqwest::{Client, ClientBuilder, Url};
-use ring::digest::{Context, Digest, SHA256};
-use std::{collections::HashSet, env::consts::OS, path::Path, sync::LazyLock, time::Duration};
+use std::{
+    collections::{BTreeSet, HashMap, HashSet, VecDeque},
+    env::consts::OS,
+    path::Path,
+    sync::atomic::{AtomicU64, Ordering},
+    time::{Duration, Instant},
+};
 use tokio::time::sleep;
 
-use super::nodestructs::{Node, PushshiftBase, RawNode};
+use super::{
+    bots::BotRecord,
+    cache::ResponseCache,
+    events::{Observers, ScrapeEvent, ScrapeObserver},
+    hashing::{is_hashed, NameHasher},
+    nodefilter::FilterPipeline,
+    nodestructs::{AggKey, Edge, Node, PushshiftBase, PushshiftMetadata, RawNode},
+    quota::{Quota, Quotas},
+    stop::{ScrapeOutcome, ScrapeState, StopCondition},
+    stratified::{allocate, split_period, Allocation, Stratum},
+};
 use crate::{
-    nodecsv::nodeio::{read_nodes, write_nodes},
-    pushshift::PSError,
+    nodecsv::nodeio::{
+        read_nodes, write_bots, write_edges, write_nodes, write_raw_nodes, write_strata,
+    },
+    pushshift::{
+        sortopts::{Aggregation, Sort},
+        PSEndpoint, PSError, PushshiftBuilder,
+    },
 };
 
-static DEFAULT_BACKOFF: u64 = 10;
+/// Seconds to sleep between requests unless set with ScraperClient::set_backoff().
+pub static DEFAULT_BACKOFF: u64 = 10;
+// The API returns 25 items if size isn't set.
+static DEFAULT_PAGE_SIZE: u64 = 25;
 static DEFAULT_THRESH: u8 = 3;
-static TOPIC_POS: usize = 5;
-// Invalid or unusable scraped data.
-static NOT_USERS: LazyLock<Vec<String>> =
-    LazyLock::new(|| vec!["[deleted]".to_string(), "AutoModerator".to_string()]);
+
+// Where a stream() is within its query.
+struct StreamState {
+    // Next page or None once the query is exhausted or failed
+    url: Option<Url>,
+    // Items of the current page that weren't yielded yet
+    page: VecDeque<RawNode>,
+    // Seconds to sleep before the next request. None before the first one.
+    backoff: Option<u64>,
+    failures: u8,
+}
+
+impl StreamState {
+    // Counts a failed request and returns whether to retry it. Retries back off exponentially
+    // starting from `base`.
+    fn retry(&mut self, error: &PSError, base: u64) -> bool {
+        self.failures += 1;
+        if !error.is_retryable() || self.failures >= DEFAULT_THRESH {
+            return false;
+        }
+        self.backoff = Some(grow_backoff(self.backoff.unwrap_or(base)));
+        true
+    }
+}
+
+// Squares the backoff up to a maximum of 60 seconds. Short backoffs start at 2 so that they still
+// grow.
+fn grow_backoff(seconds: u64) -> u64 {
+    std::cmp::min(seconds.max(2).saturating_pow(2), 60)
+}
+
+/// Progress of a single paginated query (i.e. a subreddit or batch of subreddits).
+#[derive(Clone, Debug, Default)]
+pub struct QueryProgress {
+    /// Items received so far.
+    pub scraped: u64,
+    /// Items received so far per lowercase subreddit. Batched queries cover several.
+    pub per_subreddit: HashMap<String, u64>,
+    /// Items left according to the last response's metadata.
+    pub remaining: Option<u64>,
+    /// Items per request.
+    pub page_size: u64,
+}
+
+impl QueryProgress {
+    /// Requests left to exhaust the query if the API reported its total.
+    pub fn remaining_requests(&self) -> Option<u64> {
+        self.remaining
+            .map(|remaining| remaining.div_ceil(self.page_size.max(1)))
+    }
+}
 
 #[derive(Debug)]
 pub struct ScraperClient {
     backoff_time: u64,
+    // Backoff to return to after a successful round
+    base_backoff: u64,
+    cache: Option<ResponseCache>,
     client: Client,
+    edges: Vec<Edge>,
+    // Queries dropped because they ran out of items
+    exhausted: usize,
+    // Queries dropped because they failed DEFAULT_THRESH times in a row
+    failed: usize,
+    // Consecutive failures per query
+    failures: HashMap<String, u8>,
+    filters: FilterPipeline,
     nodes: HashSet<Node>,
+    observers: Observers,
+    // Oldest created_utc received during this run
+    oldest: Option<u64>,
+    progress: HashMap<String, QueryProgress>,
+    quotas: Option<Quotas>,
+    // Accepted items with every field if they are kept
+    raw: Option<Vec<RawNode>>,
+    requests: AtomicU64,
+    started: Instant,
+    strata: Vec<Stratum>,
     urls: Vec<Url>,
     zero_length_scrapes: u8,
 }
@@ -34,8 +125,23 @@ impl ScraperClient {
     pub fn new(timeout: u64, urls: &[Url]) -> Result<Self, PSError> {
         Ok(ScraperClient {
             backoff_time: DEFAULT_BACKOFF,
+            base_backoff: DEFAULT_BACKOFF,
+            cache: None,
             client: ScraperClient::make_client(timeout)?,
+            edges: Vec::new(),
+            exhausted: 0,
+            failed: 0,
+            failures: HashMap::new(),
+            filters: FilterPipeline::default(),
             nodes: HashSet::new(),
+            observers: Observers::default(),
+            oldest: None,
+            progress: HashMap::new(),
+            quotas: None,
+            raw: None,
+            requests: AtomicU64::new(0),
+            started: Instant::now(),
+            strata: Vec::new(),
             urls: urls.to_vec(),
             zero_length_scrapes: 0,
         })
@@ -58,13 +164,111 @@ impl ScraperClient {
     where
         P: AsRef<Path>,
     {
-        Ok(ScraperClient {
-            backoff_time: DEFAULT_BACKOFF,
-            client: ScraperClient::make_client(timeout)?,
-            urls: urls.to_vec(),
-            nodes: read_nodes(path)?,
-            zero_length_scrapes: 0,
-        })
+        Self::from_nodes(timeout, urls, read_nodes(path)?)
+    }
+
+    /// Resumes a scrape from nodes that were already loaded, e.g. with NodeFormat::read().
+    /// Each query continues past the loaded nodes of its subreddits instead of starting over.
+    pub fn from_nodes(timeout: u64, urls: &[Url], nodes: HashSet<Node>) -> Result<Self, PSError> {
+        let urls = urls
+            .iter()
+            .map(|url| ScraperClient::resume_cursor(url, &nodes))
+            .collect::<Result<Vec<_>, _>>()?;
+        let mut scraper = Self::new(timeout, &urls)?;
+        scraper.nodes = nodes;
+        Ok(scraper)
+    }
+
+    // Moves the cursor of `url` to the oldest (or newest if ascending) of `nodes` in its
+    // subreddits. Batched queries page through their subreddits together so they share one.
+    fn resume_cursor(url: &Url, nodes: &HashSet<Node>) -> Result<Url, PSError> {
+        let subreddits: HashSet<String> = url
+            .query_pairs()
+            .find(|(param, _)| param == "subreddit")
+            .map(|(_, subs)| subs.split(',').map(str::to_lowercase).collect())
+            .unwrap_or_default();
+        let stamps = nodes
+            .iter()
+            .filter(|node| {
+                subreddits.is_empty() || subreddits.contains(&node.subreddit.to_lowercase())
+            })
+            .map(|node| node.created_utc);
+        let ascending = ScraperClient::is_ascending(url);
+        let cursor = if ascending {
+            stamps.max()
+        } else {
+            stamps.min()
+        };
+        match cursor {
+            Some(cursor) => ScraperClient::replace_cursor(url.as_str(), ascending, cursor),
+            None => Ok(url.clone()),
+        }
+    }
+
+    /// Sets the seconds to sleep between requests. Failed rounds still back off exponentially.
+    pub fn set_backoff(&mut self, seconds: u64) -> &mut Self {
+        self.backoff_time = seconds;
+        self.base_backoff = seconds;
+        self
+    }
+
+    /// Replaces the default filters, which only drop deleted accounts and AutoModerator.
+    pub fn set_filters(&mut self, filters: FilterPipeline) -> &mut Self {
+        self.filters = filters;
+        self
+    }
+
+    /// Serves responses from `cache` and records new ones depending on its mode. Replaying
+    /// also skips the sleeps between requests since nothing reaches the API.
+    pub fn set_cache(&mut self, cache: ResponseCache) -> &mut Self {
+        self.cache = Some(cache);
+        self
+    }
+
+    /// Keeps every accepted item with all of its fields for OutputMode::Raw, starting with
+    /// `existing` when resuming. hash_names() leaves them alone since bodies and titles can't
+    /// be hashed.
+    pub fn keep_raw<I>(&mut self, existing: I) -> &mut Self
+    where
+        I: IntoIterator<Item = RawNode>,
+    {
+        self.raw = Some(existing.into_iter().collect());
+        self
+    }
+
+    /// Accepted items if keep_raw() was called.
+    pub fn view_raw(&self) -> &[RawNode] {
+        self.raw.as_deref().unwrap_or_default()
+    }
+
+    pub fn raw_to_ndjson<P>(&self, path: P) -> Result<(), PSError>
+    where
+        P: AsRef<Path>,
+    {
+        write_raw_nodes(path, self.view_raw())
+    }
+
+    /// Sends every ScrapeEvent to `observer` in addition to any earlier observers.
+    pub fn add_observer<O: ScrapeObserver + 'static>(&mut self, observer: O) -> &mut Self {
+        self.observers.push(observer);
+        self
+    }
+
+    /// Number of items each filter rejected so far.
+    pub fn view_rejections(&self) -> Vec<(&'static str, usize)> {
+        self.filters.rejections()
+    }
+
+    /// Accounts dropped as bots so far.
+    pub fn view_bots(&self) -> Vec<BotRecord> {
+        self.filters.bots().audit()
+    }
+
+    pub fn bots_to_csv<P>(&self, path: P) -> Result<(), PSError>
+    where
+        P: AsRef<Path>,
+    {
+        write_bots(path, &self.view_bots())
     }
 
     pub fn to_csv<P>(&self, path: P) -> Result<(), PSError>
@@ -74,6 +278,169 @@ impl ScraperClient {
         write_nodes(path, &self.nodes)
     }
 
+    pub fn edges_to_csv<P>(&self, path: P) -> Result<(), PSError>
+    where
+        P: AsRef<Path>,
+    {
+        write_edges(path, &self.edges)
+    }
+
+    pub fn view_edges(&self) -> &[Edge] {
+        &self.edges
+    }
+
+    pub fn strata_to_csv<P>(&self, path: P) -> Result<(), PSError>
+    where
+        P: AsRef<Path>,
+    {
+        write_strata(path, &self.strata)
+    }
+
+    pub fn view_strata(&self) -> &[Stratum] {
+        &self.strata
+    }
+
+    /// Progress per query keyed by the query's subreddit parameter.
+    pub fn view_progress(&self) -> &HashMap<String, QueryProgress> {
+        &self.progress
+    }
+
+    /// Estimates the requests and time left to exhaust every query under the current backoff.
+    /// Queries that never reported metadata are ignored.
+    pub fn estimate_remaining(&self) -> (u64, Duration) {
+        let requests: u64 = self
+            .progress
+            .values()
+            .filter_map(QueryProgress::remaining_requests)
+            .sum();
+        (requests, Duration::from_secs(requests * self.backoff_time))
+    }
+
+    // Queries are identified by their subreddits since the "before" cursor changes each page.
+    fn progress_key(url: &Url) -> String {
+        url.query_pairs()
+            .find(|(param, _)| param == "subreddit")
+            .map_or_else(|| String::from("*"), |(_, subs)| subs.into_owned())
+    }
+
+    fn page_size(url: &Url) -> u64 {
+        url.query_pairs()
+            .find(|(param, _)| param == "size")
+            .and_then(|(_, size)| size.parse().ok())
+            .unwrap_or(DEFAULT_PAGE_SIZE)
+    }
+
+    fn record_progress(
+        &mut self,
+        url: &Url,
+        data: &[RawNode],
+        metadata: Option<&PushshiftMetadata>,
+    ) {
+        let received = data.len();
+        let progress = self
+            .progress
+            .entry(Self::progress_key(url))
+            .or_insert_with(|| QueryProgress {
+                page_size: Self::page_size(url),
+                ..Default::default()
+            });
+        progress.scraped += received as u64;
+        // Batched URLs query several subreddits at once so split the results back.
+        for raw in data {
+            *progress
+                .per_subreddit
+                .entry(raw.subreddit().to_lowercase())
+                .or_insert(0) += 1;
+        }
+
+        if let Some(metadata) = metadata {
+            debug!(
+                "{} took {}ms with {}/{} shards.",
+                url,
+                metadata.execution_time_milliseconds,
+                metadata.shards.successful,
+                metadata.shards.total
+            );
+            if metadata.is_partial() {
+                warn!(
+                    "Results may be incomplete for {}: {} failed shards, timed out: {}",
+                    url, metadata.shards.failed, metadata.timed_out
+                );
+            }
+            if let Some(remaining) = metadata.remaining(received) {
+                progress.remaining = Some(remaining);
+            }
+        }
+        let remaining = progress.remaining;
+        self.observers.emit(|| ScrapeEvent::PageParsed {
+            url: url.to_string(),
+            items: received,
+            remaining,
+        });
+    }
+
+    // Logs a failed request or page and tells the observers.
+    fn report_error(&self, url: &Url, error: &PSError) {
+        error!("{}", error.report());
+        self.observers.emit(|| ScrapeEvent::Error {
+            url: url.to_string(),
+            message: error.report(),
+            retryable: error.is_retryable(),
+        });
+    }
+
+    // Runs the filters on an item and tells the observers if one rejects it.
+    fn passes_filters(&mut self, raw: &RawNode) -> bool {
+        match self.filters.rejection(raw) {
+            Some(filter) => {
+                self.observers.emit(|| ScrapeEvent::NodeRejected { filter });
+                false
+            }
+            None => true,
+        }
+    }
+
+    // Adds a node parsed from `raw` and tells the observers unless it's a duplicate.
+    fn insert_node(&mut self, raw: &RawNode, node: Node) -> bool {
+        let subreddit = node.subreddit.to_lowercase();
+        if !self.nodes.insert(node) {
+            return false;
+        }
+        if let Some(kept) = &mut self.raw {
+            kept.push(raw.clone());
+        }
+        let total = self.nodes.len();
+        self.observers
+            .emit(|| ScrapeEvent::NodeAccepted { subreddit, total });
+        true
+    }
+
+    fn log_progress(&self) {
+        for (query, progress) in self.progress.iter() {
+            match progress.remaining {
+                Some(remaining) => info!(
+                    "r/{}: {} scraped, {} remaining.",
+                    query, progress.scraped, remaining
+                ),
+                None => info!("r/{}: {} scraped.", query, progress.scraped),
+            }
+        }
+
+        let (requests, time) = self.estimate_remaining();
+        if requests > 0 {
+            info!(
+                "Estimated {} requests left taking at least {} minutes.",
+                requests,
+                time.as_secs() / 60
+            );
+        }
+    }
+
+    /// Requests sent so far including failed ones.
+    pub fn requests(&self) -> u64 {
+        self.requests.load(Ordering::Relaxed)
+    }
+
     pub fn length_nodes(&self) -> usize {
         self.nodes.len()
     }
@@ -82,34 +449,34 @@ impl ScraperClient {
         &self.nodes
     }
 
-    // Encode bytes with SHA256 using Ring
-    fn sha256(data: &[u8]) -> Digest {
-        let mut context = Context::new(&SHA256);
-        context.update(data);
-        context.finish()
+    /// Number of nodes per subreddit. Subreddits are lowercased since batched URLs return the
+    /// canonical capitalization which may differ from the requested one.
+    pub fn subreddit_counts(&self) -> HashMap<String, usize> {
+        let mut counts = HashMap::new();
+        for node in self.nodes.iter() {
+            *counts.entry(node.subreddit.to_lowercase()).or_insert(0) += 1;
+        }
+        counts
     }
 
-    /// Hashes names of posters/topics.
-    /// Topics are extracted from the _permalink_ field.
-    pub fn hash_names(&mut self) {
+    /// Hashes names of posters/topics, keyed by `key` if given. See NameHasher.
+    ///
+    /// Nodes that are already hashed, e.g. those loaded to resume a hashed scrape, are kept as
+    /// they are so new nodes that hash to one of them are dropped as duplicates.
+    pub fn hash_names(&mut self, key: Option<&[u8]>) {
+        let hasher = NameHasher::new(key);
         let mut hashed_names = self
             .nodes
             .drain()
-            .map(|old_node| Node {
-                author: hex::encode(Self::sha256(old_node.author.as_bytes())),
-                created_utc: old_node.created_utc,
-                permalink: old_node
-                    .permalink
-                    .split('/')
-                    .nth(TOPIC_POS)
-                    .map_or(String::from("NA"), |topic| {
-                        hex::encode(Self::sha256(topic.as_bytes()))
-                    }),
-                subreddit: old_node.subreddit,
-            })
+            .map(|node| hasher.hash_node(node))
             .collect();
 
         std::mem::swap(&mut self.nodes, &mut hashed_names);
+
+        for edge in self.edges.iter_mut() {
+            hasher.hash_edge(edge);
+        }
+        self.filters.bots_mut().hash_names(&hasher);
     }
 
     /// Snowball samples edges by using each unique username to gather a list of subreddits to
@@ -130,82 +497,525 @@ impl ScraperClient {
             users_deser.extend(self.client.get(user_url).send().await?.json().await);
             self.backoff().await;
         }
-        self.nodes.extend(users_deser.into_iter());
+        self.nodes.extend(users_deser);
 
         Ok(())
     }
 
-    /// Scrapes until node_limit is reached.
-    pub async fn scrape_until(&mut self, node_limit: usize) -> Result<(), PSError> {
-        while self.length_nodes() < node_limit {
-            info!("Node length: {}", self.length_nodes());
-            if self.scrape_nodes().await? == 0 {
-                // This whole paradigm is ugly. I need to clean it up.
-                if self.zero_length_scrapes == DEFAULT_THRESH {
-                    return Err(PSError::NoMoreNodes);
+    /// Snowball samples weighted edges by asking the API for each unique author's subreddit
+    /// distribution. Unlike scrape_individ_users() this costs one request per author instead
+    /// of paging through their entire history.
+    pub async fn scrape_author_subreddits(
+        &mut self,
+        endpoint: PSEndpoint,
+        min_doc_count: u32,
+    ) -> Result<(), PSError> {
+        // Sorted so that replayed scrapes write their edges in the same order. Hashed names,
+        // e.g. those loaded to resume a hashed scrape, can't be looked up.
+        let (hashed, authors): (BTreeSet<_>, BTreeSet<_>) = self
+            .nodes
+            .iter()
+            .map(|node| node.author.clone())
+            .partition(|author| is_hashed(author));
+        if !hashed.is_empty() {
+            warn!("Skipping {} authors whose names are hashed.", hashed.len());
+        }
+        info!("Aggregating subreddits for {} authors.", authors.len());
+
+        for author in authors.iter() {
+            let url = match PushshiftBuilder::new(endpoint)
+                .author(&[author])
+                .and_then(|builder| builder.aggs(Aggregation::Subreddit))
+                .and_then(|builder| builder.min_doc_count(min_doc_count))
+                .and_then(|builder| builder.size(0))
+                .and_then(|builder| builder.build())
+            {
+                Ok(url) => url,
+                // Some (very old) usernames don't pass validation. Skip them.
+                Err(error) => {
+                    error!("Skipping author {}: {}", author, error);
+                    continue;
                 }
-                self.exponential_backoff();
+            };
+
+            match self.fetch(&url).await {
+                Ok(scraped) => {
+                    let buckets = scraped
+                        .aggs
+                        .get(&Aggregation::Subreddit.to_string())
+                        .map(Vec::as_slice)
+                        .unwrap_or_default();
+                    debug!("{} posted in {} subreddits.", author, buckets.len());
+                    self.edges
+                        .extend(buckets.iter().filter_map(|bucket| match &bucket.key {
+                            AggKey::Text(subreddit) => Some(Edge {
+                                author: author.clone(),
+                                subreddit: subreddit.clone(),
+                                weight: bucket.doc_count,
+                            }),
+                            AggKey::Time(_) => None,
+                        }));
+                }
+                Err(error) => self.report_error(&url, &error),
+            }
+            self.backoff().await;
+        }
+
+        Ok(())
+    }
+
+    /// Samples `budget` nodes from `[start, end)` by splitting the period into equal time
+    /// buckets for each subreddit. Each Node records its bucket so that the sample can be
+    /// weighted by the strata's populations later on. Nodes the scraper already holds, e.g.
+    /// when resuming, count towards the strata they fall into.
+    ///
+    /// `template` holds every parameter except for the subreddit and time window.
+    #[allow(clippy::too_many_arguments)]
+    pub async fn scrape_stratified<T: AsRef<str>>(
+        &mut self,
+        template: &PushshiftBuilder,
+        subs: &[T],
+        start: u64,
+        end: u64,
+        buckets: u32,
+        budget: usize,
+        allocation: Allocation,
+    ) -> Result<ScrapeOutcome, PSError> {
+        let mut strata = Vec::new();
+        let mut urls = Vec::new();
+        for sub in subs.iter().map(AsRef::as_ref) {
+            for (bucket, (window_start, window_end)) in (0..).zip(split_period(start, end, buckets))
+            {
+                urls.push(
+                    template
+                        .clone()
+                        .subreddit(sub)?
+                        .window(window_start, window_end)?
+                        .build()?,
+                );
+                strata.push(Stratum {
+                    subreddit: sub.to_string(),
+                    bucket,
+                    start: window_start,
+                    end: window_end,
+                    population: None,
+                    target: 0,
+                    scraped: 0,
+                });
+            }
+        }
+
+        // Proportional allocation needs the size of each bucket which the metadata provides
+        // without downloading any items.
+        if allocation == Allocation::Proportional {
+            for (stratum, url) in strata.iter_mut().zip(urls.iter()) {
+                stratum.population = self.population(url).await?;
             }
-            self.zero_length_scrapes = 0
         }
+        allocate(&mut strata, budget, allocation);
+        // Resumed samples already hold some of each stratum's nodes.
+        for stratum in strata.iter_mut() {
+            stratum.scraped = self
+                .nodes
+                .iter()
+                .filter(|node| stratum.contains(node))
+                .count();
+        }
+
+        for (stratum, mut url) in strata.iter_mut().zip(urls) {
+            info!(
+                "Sampling {} nodes from r/{} bucket {} [{}, {}).",
+                stratum.target, stratum.subreddit, stratum.bucket, stratum.start, stratum.end
+            );
+            let mut failures = 0;
+            while stratum.scraped < stratum.target && failures < DEFAULT_THRESH {
+                let scraped = match self.fetch(&url).await {
+                    Ok(scraped) => scraped,
+                    Err(error) => {
+                        self.report_error(&url, &error);
+                        failures = if error.is_retryable() {
+                            failures + 1
+                        } else {
+                            DEFAULT_THRESH
+                        };
+                        self.backoff().await;
+                        continue;
+                    }
+                };
+                self.record_progress(&url, &scraped.data, scraped.metadata.as_ref());
+
+                let next = ScraperClient::next_page(&url, &scraped.data)?;
+                for raw in scraped.data.iter() {
+                    if stratum.scraped == stratum.target {
+                        break;
+                    }
+                    let mut node: Node = raw.into();
+                    node.stratum = Some(stratum.bucket);
+                    if self.passes_filters(raw) && self.insert_node(raw, node) {
+                        stratum.scraped += 1;
+                    }
+                }
+                self.backoff().await;
+
+                match next {
+                    Some(next) => url = next,
+                    None => {
+                        info!("No more nodes in: {}", url);
+                        self.observers.emit(|| ScrapeEvent::CursorExhausted {
+                            url: url.to_string(),
+                        });
+                        break;
+                    }
+                }
+            }
+
+            if stratum.scraped < stratum.target {
+                warn!(
+                    "Only sampled {}/{} nodes from r/{} bucket {}.",
+                    stratum.scraped, stratum.target, stratum.subreddit, stratum.bucket
+                );
+            }
+        }
+
+        // Accounts flagged by their behaviour may have had items accepted before they were.
+        self.strata = strata;
+        self.drop_retracted();
+        let outcome = if self
+            .strata
+            .iter()
+            .all(|stratum| stratum.scraped >= stratum.target)
+        {
+            ScrapeOutcome::ReachedTarget
+        } else {
+            ScrapeOutcome::Exhausted
+        };
+        Ok(outcome)
+    }
+
+    // Asks the API for the number of items matching `url` without downloading any of them.
+    async fn population(&self, url: &Url) -> Result<Option<u64>, PSError> {
+        let probe =
+            ScraperClient::replace_param(url.as_str(), "size", &[], "0").and_then(|probe| {
+                ScraperClient::replace_param(probe.as_str(), "metadata", &[], "true")
+            })?;
+        let population = match self.fetch(&probe).await {
+            Ok(scraped) => scraped.metadata.and_then(|metadata| metadata.total_results),
+            Err(error) => {
+                self.report_error(&probe, &error);
+                None
+            }
+        };
+        self.backoff().await;
+        Ok(population)
+    }
+
+    /// Number of items in each subreddit according to the API using `template`, which holds every
+    /// parameter except for the subreddit. Subreddits whose probe failed are None.
+    pub async fn populations<T: AsRef<str>>(
+        &self,
+        template: &PushshiftBuilder,
+        subs: &[T],
+    ) -> Result<Vec<(String, Option<u64>)>, PSError> {
+        let mut populations = Vec::new();
+        for sub in subs.iter().map(AsRef::as_ref) {
+            let url = template
+                .clone()
+                .subreddit(sub)
+                .and_then(|builder| builder.build())
+                .map_err(PSError::for_subreddit(sub))?;
+            let population = self
+                .population(&url)
+                .await
+                .map_err(PSError::for_subreddit(sub))?;
+            if let Some(population) = population {
+                info!("r/{} has {} items.", sub, population);
+            }
+            populations.push((sub.to_lowercase(), population));
+        }
+        Ok(populations)
+    }
+
+    /// Limits how many nodes scrape_until() takes from each subreddit out of `budget`.
+    /// Proportional quotas ask the API for each subreddit's size using `template`, which holds
+    /// every parameter except for the subreddit.
+    pub async fn set_quotas<T: AsRef<str>>(
+        &mut self,
+        quota: Quota,
+        budget: usize,
+        template: &PushshiftBuilder,
+        subs: &[T],
+    ) -> Result<(), PSError> {
+        let mut populations = HashMap::new();
+        if quota == Quota::Proportional {
+            for (sub, population) in self.populations(template, subs).await? {
+                if let Some(population) = population {
+                    populations.insert(sub, population);
+                }
+            }
+        }
+
+        self.quotas = Some(Quotas::new(quota, budget, subs, &populations));
+        Ok(())
+    }
+
+    /// Achieved and target node counts per subreddit if quotas are set.
+    pub fn quota_report(&self) -> Vec<(String, usize, usize)> {
+        self.quotas
+            .as_ref()
+            .map(|quotas| quotas.report(&self.subreddit_counts()))
+            .unwrap_or_default()
+    }
+
+    // Drops subreddits that met their quota from the URLs. URLs without any subreddits left
+    // are dropped entirely.
+    fn retire_met_quotas(&mut self) -> Result<(), PSError> {
+        let Some(quotas) = &self.quotas else {
+            return Ok(());
+        };
+        let counts = self.subreddit_counts();
+
+        let mut urls = Vec::with_capacity(self.urls.len());
+        for url in std::mem::take(&mut self.urls) {
+            // Queries without subreddits can't be retired by subreddit quotas.
+            if !url.query_pairs().any(|(param, _)| param == "subreddit") {
+                urls.push(url);
+                continue;
+            }
+            let key = ScraperClient::progress_key(&url);
+            let subs: Vec<&str> = key.split(',').collect();
+            let open: Vec<&str> = subs
+                .iter()
+                .copied()
+                .filter(|sub| quotas.accepts(sub, &counts))
+                .collect();
+
+            if open.len() == subs.len() {
+                urls.push(url);
+                continue;
+            }
+            for sub in subs.iter().filter(|sub| !open.contains(sub)) {
+                info!("Retiring r/{}: quota of {} met.", sub, quotas.target(sub));
+            }
+            if !open.is_empty() {
+                let url =
+                    ScraperClient::replace_param(url.as_str(), "subreddit", &[], &open.join(","))?;
+                // Progress follows the query to its new key.
+                if let Some(progress) = self.progress.remove(&key) {
+                    self.progress
+                        .insert(ScraperClient::progress_key(&url), progress);
+                }
+                urls.push(url);
+            }
+        }
+
+        self.urls = urls;
+        Ok(())
+    }
+
+    /// Number of distinct authors among the nodes scraped so far.
+    pub fn unique_authors(&self) -> usize {
+        self.nodes
+            .iter()
+            .map(|node| node.author.as_str())
+            .collect::<HashSet<_>>()
+            .len()
+    }
+
+    /// Snapshot of the scrape for checking stop conditions.
+    pub fn scrape_state(&self, discovery_rate: Option<f64>) -> ScrapeState {
+        ScrapeState {
+            nodes: self.length_nodes(),
+            authors: self.unique_authors(),
+            oldest: self.oldest,
+            elapsed: self.started.elapsed(),
+            requests: self.requests.load(Ordering::Relaxed),
+            discovery_rate,
+        }
+    }
+
+    /// Scrapes until `stop` is met, every query is exhausted, or every query keeps failing.
+    /// Errors are only returned for problems that aren't specific to a query.
+    pub async fn scrape_until(&mut self, stop: &StopCondition) -> Result<ScrapeOutcome, PSError> {
+        self.started = Instant::now();
+        self.zero_length_scrapes = 0;
+        let mut discovery_rate = None;
+        let outcome = loop {
+            let state = self.scrape_state(discovery_rate);
+            if stop.is_met(&state) {
+                info!("Stop condition met: {:?}", state);
+                break if stop.is_target_met(&state) {
+                    ScrapeOutcome::ReachedTarget
+                } else {
+                    ScrapeOutcome::BudgetHit
+                };
+            }
+            if self
+                .quotas
+                .as_ref()
+                .is_some_and(|quotas| quotas.all_met(&self.subreddit_counts()))
+            {
+                info!("All subreddit quotas met.");
+                break ScrapeOutcome::ReachedTarget;
+            }
+            if self.urls.is_empty() {
+                info!(
+                    "No queries left: {} exhausted, {} failed.",
+                    self.exhausted, self.failed
+                );
+                break if self.exhausted == 0 && self.failed > 0 {
+                    ScrapeOutcome::Aborted
+                } else {
+                    ScrapeOutcome::Exhausted
+                };
+            }
+            if self.zero_length_scrapes >= DEFAULT_THRESH {
+                error!(
+                    "Giving up after {} rounds without any new nodes.",
+                    self.zero_length_scrapes
+                );
+                break ScrapeOutcome::Aborted;
+            }
+            info!(
+                "Node length: {}; unique authors: {}",
+                state.nodes, state.authors
+            );
+            debug!("Nodes per subreddit: {:?}", self.subreddit_counts());
+            self.log_progress();
+
+            let scraped = self.scrape_nodes().await?;
+            if scraped > 0 {
+                // Saturation is measured as newly discovered authors per item received.
+                let found = self.unique_authors().saturating_sub(state.authors);
+                discovery_rate = Some(found as f64 / scraped as f64);
+                debug!("Author discovery rate: {:?}", discovery_rate);
+                self.backoff_time = self.base_backoff;
+            } else if !self.urls.is_empty() {
+                // Every remaining query failed this round so slow down before retrying.
+                self.exponential_backoff();
+            }
+            // Failing queries are dropped on their own, but queries whose pages only repeat
+            // nodes we already have would otherwise go on until the stop condition.
+            if self.length_nodes() > state.nodes || self.urls.is_empty() {
+                self.zero_length_scrapes = 0;
+            } else {
+                self.zero_length_scrapes += 1;
+            }
+        };
         debug!(
-            "scrape_until ended with {}/{}.",
+            "scrape_until ended with {} nodes after {} requests.",
             self.length_nodes(),
-            node_limit
+            self.requests.load(Ordering::Relaxed)
         );
-        Ok(())
+        for (subreddit, achieved, target) in self.quota_report() {
+            info!("r/{}: {}/{} nodes.", subreddit, achieved, target);
+        }
+        Ok(outcome)
     }
 
-    // Non-accounts such as deleted posts/users are scraped as well.
-    fn filter_junk(&mut self) {
-        // I have to filter here because I'm comparing the usernames which are NOT nodes.
-        // Therefore, I can't use set logic.
-        let bad_nodes: Vec<_> = self
-            .nodes
-            .iter()
-            .filter(|node| NOT_USERS.iter().any(|nonuser| *nonuser == node.author))
-            .cloned()
+    // Drops the earlier items of accounts that the bot filter flagged since the last call.
+    fn drop_retracted(&mut self) {
+        let authors: HashSet<_> = self
+            .filters
+            .bots_mut()
+            .take_retracted()
+            .into_iter()
             .collect();
+        if authors.is_empty() {
+            return;
+        }
 
-        for bad_node in bad_nodes {
-            debug!("Bad node: {:?}", bad_node);
-            self.nodes.remove(&bad_node);
+        if let Some(kept) = &mut self.raw {
+            kept.retain(|raw| !authors.contains(raw.author()));
         }
+        let strata = &mut self.strata;
+        self.nodes.retain(|node| {
+            if !authors.contains(&node.author) {
+                return true;
+            }
+            debug!("Bad node: {:?}", node);
+            if let Some(stratum) = strata.iter_mut().find(|stratum| stratum.contains(node)) {
+                stratum.scraped = stratum.scraped.saturating_sub(1);
+            }
+            false
+        });
     }
 
     // Sleeps before resuming a scrape.
     async fn backoff(&self) {
+        if self.cache.as_ref().is_some_and(ResponseCache::is_offline) {
+            return;
+        }
         info!("Sleeping: {} seconds", self.backoff_time);
+        self.observers.emit(|| ScrapeEvent::BackoffStarted {
+            seconds: self.backoff_time,
+        });
         sleep(Duration::from_secs(self.backoff_time)).await;
     }
 
     fn exponential_backoff(&mut self) {
-        // Increase the backoff timer by timer^2 up to a maximum of 60
-        self.backoff_time = std::cmp::max(self.backoff_time.pow(2), 60);
+        self.backoff_time = grow_backoff(self.backoff_time);
+    }
+
+    // Descending scrapes page backwards by moving "before" to the oldest item while ascending
+    // scrapes page forwards by moving "after" to the newest item. The other bound of the window
+    // is left alone.
+    fn is_ascending(url: &Url) -> bool {
+        url.query_pairs()
+            .any(|(param, order)| param == "sort" && order == Sort::Asc.to_string())
+    }
+
+    fn replace_cursor(url: &str, ascending: bool, epoch: u64) -> Result<Url, PSError> {
+        // The cursor replaces its alias as well since the new page is always inside the window.
+        let (cursor, alias) = if ascending {
+            ("after", "since")
+        } else {
+            ("before", "until")
+        };
+        ScraperClient::replace_param(url, cursor, &[alias], &epoch.to_string())
+    }
+
+    // Returns the URL for the page after `data` or None if the query is exhausted.
+    fn next_page(url: &Url, data: &[RawNode]) -> Result<Option<Url>, PSError> {
+        let ascending = ScraperClient::is_ascending(url);
+        let stamps = data.iter().map(|node| node.created_utc);
+        // Find the lowest (descending) or highest (ascending) date-time stamp for the new cursor.
+        let cursor = if ascending {
+            stamps.max()
+        } else {
+            stamps.min()
+        };
+        cursor
+            .map(|cursor| ScraperClient::replace_cursor(url.as_str(), ascending, cursor))
+            .transpose()
     }
 
-    fn replace_before(url: &str, epoch: u64) -> Result<Url, PSError> {
-        debug!("Replacing parameter before in: {}", url);
+    // Replaces `param` and removes any of its `aliases` from `url`.
+    fn replace_param(
+        url: &str,
+        param: &str,
+        aliases: &[&str],
+        value: &str,
+    ) -> Result<Url, PSError> {
+        debug!("Replacing parameter {} in: {}", param, url);
         // First we need to turn the &str to a Url. This shouldn't fail since the string slice
         // directly comes from an already parsed URL. However, I check anyway because I'm not sure
         // what the paradigm is here yet.
-        let new_url = Url::parse(url).map_err(|error| {
+        let new_url = Url::parse(url).inspect_err(|error| {
             error!(
                 "Failed to parse URL: {} while paginating. Error: {}",
                 url, error
             );
-            error
         })?;
-        // Next, the "before" query must be filtered out of the URL followed by pushing the new
+        // Next, the old query must be filtered out of the URL followed by pushing the new
         // query into the Vector.
         // I can't figure out how to change the actual query without going through this lengthy
         // filtering process.
         let mut qpairs: Vec<_> = new_url
             .query_pairs()
             .into_owned()
-            .filter(|query_pair| query_pair.0 != "before")
+            .filter(|query_pair| query_pair.0 != param && !aliases.contains(&&*query_pair.0))
             .collect();
-        qpairs.push((String::from("before"), epoch.to_string()));
+        qpairs.push((String::from(param), value.to_string()));
 
         // If the URL doesn't contain a host then something is hopelessly wrong
         Ok(Url::parse_with_params(
@@ -218,59 +1028,665 @@ impl ScraperClient {
         )?)
     }
 
+    /// Streams every item matching `url` page by page until the query runs out.
+    ///
+    /// Requests are spaced by the backoff set with set_backoff(). Failures that may succeed
+    /// later are retried with exponential backoff; the stream yields the error and ends once a
+    /// request fails DEFAULT_THRESH times in a row or can't succeed at all. Items aren't filtered
+    /// or added to the scraper's nodes.
+    ///
+    /// ```no_run
+    /// use futures::StreamExt;
+    /// use thesis_gamer_scraper::{PSEndpoint, PushshiftBuilder, ScraperClient};
+    ///
+    /// # async fn sample() -> Result<(), thesis_gamer_scraper::PSError> {
+    /// let url = PushshiftBuilder::new(PSEndpoint::Comment)
+    ///     .subreddit("gaming")?
+    ///     .build()?;
+    /// let scraper = ScraperClient::new(30, &[])?;
+    /// let mut pages = scraper.stream(&url).take(5000).chunks(1000);
+    /// while let Some(chunk) = pages.next().await {
+    ///     let items = chunk.into_iter().collect::<Result<Vec<_>, _>>()?;
+    ///     println!("{} items", items.len());
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn stream(&self, url: &Url) -> BoxStream<'_, Result<RawNode, PSError>> {
+        let state = StreamState {
+            url: Some(url.clone()),
+            page: VecDeque::new(),
+            backoff: None,
+            failures: 0,
+        };
+
+        stream::unfold(state, move |mut state| async move {
+            loop {
+                if let Some(raw) = state.page.pop_front() {
+                    return Some((Ok(raw), state));
+                }
+                let url = state.url.take()?;
+                if let Some(seconds) = state.backoff {
+                    self.observers
+                        .emit(|| ScrapeEvent::BackoffStarted { seconds });
+                    sleep(Duration::from_secs(seconds)).await;
+                }
+
+                match self.fetch(&url).await {
+                    Ok(scraped) => {
+                        debug!("Streamed {} items from {}.", scraped.data.len(), url);
+                        self.observers.emit(|| ScrapeEvent::PageParsed {
+                            url: url.to_string(),
+                            items: scraped.data.len(),
+                            remaining: scraped
+                                .metadata
+                                .as_ref()
+                                .and_then(|metadata| metadata.remaining(scraped.data.len())),
+                        });
+                        state.backoff = Some(self.base_backoff);
+                        state.failures = 0;
+                        let next = ScraperClient::next_page(&url, &scraped.data);
+                        state.page.extend(scraped.data);
+                        match next {
+                            // A page that doesn't move the cursor would repeat forever.
+                            Ok(next) => {
+                                state.url = next.filter(|next| *next != url);
+                                if state.url.is_none() {
+                                    self.observers.emit(|| ScrapeEvent::CursorExhausted {
+                                        url: url.to_string(),
+                                    });
+                                }
+                            }
+                            Err(error) => return Some((Err(error), state)),
+                        }
+                    }
+                    Err(error) => {
+                        self.observers.emit(|| ScrapeEvent::Error {
+                            url: url.to_string(),
+                            message: error.report(),
+                            retryable: error.is_retryable(),
+                        });
+                        if !state.retry(&error, self.base_backoff) {
+                            return Some((Err(error), state));
+                        }
+                        warn!("Retrying: {}", error.report());
+                        state.url = Some(url);
+                    }
+                }
+            }
+        })
+        .boxed()
+    }
+
+    async fn fetch(&self, url: &Url) -> Result<PushshiftBase, PSError> {
+        self.requests.fetch_add(1, Ordering::Relaxed);
+        self.observers.emit(|| ScrapeEvent::RequestStarted {
+            url: url.to_string(),
+        });
+        let cached = match &self.cache {
+            Some(cache) => cache.get(url)?,
+            None => None,
+        };
+        let recorded = cached.is_some();
+        let body = match cached {
+            // Only successful responses are recorded.
+            Some(body) => {
+                self.observers.emit(|| ScrapeEvent::RequestFinished {
+                    url: url.to_string(),
+                    status: Some(200),
+                    elapsed: Duration::ZERO,
+                });
+                body
+            }
+            None => self.download(url).await?,
+        };
+        let scraped = serde_json::from_slice(&body).map_err(PSError::requesting(url.as_str()))?;
+        if let Some(cache) = self.cache.as_ref().filter(|_| !recorded) {
+            cache.put(url, &body)?;
+        }
+        Ok(scraped)
+    }
+
+    // Sends the request and returns the body of a successful response.
+    async fn download(&self, url: &Url) -> Result<Vec<u8>, PSError> {
+        let started = Instant::now();
+        let response = self.client.get(url.as_str()).send().await;
+        self.observers.emit(|| ScrapeEvent::RequestFinished {
+            url: url.to_string(),
+            status: response
+                .as_ref()
+                .ok()
+                .map(|response| response.status().as_u16()),
+            elapsed: started.elapsed(),
+        });
+        let response = response.map_err(PSError::requesting(url.as_str()))?;
+        let status = response.status();
+        if !status.is_success() {
+            return Err(PSError::Status(url.to_string(), status.as_u16()));
+        }
+        let body = response
+            .bytes()
+            .await
+            .map_err(PSError::requesting(url.as_str()))?;
+        Ok(body.to_vec())
+    }
+
     // I'll refactor this after gathering my thesis data.
     // Essentially performs a convenience sample.
     pub async fn scrape_nodes(&mut self) -> Result<usize, PSError> {
         // Nodes holds RawNodes in case I decide to use the extra information
         // in any way.
-        let mut nodes: HashSet<RawNode> = HashSet::new();
-        let mut new_urls: Vec<Url> = Vec::with_capacity(self.urls.len());
-        for url_str in self.urls.iter().map(|url| url.as_str()) {
-            let result: Result<PushshiftBase, PSError> = self
-                .client
-                .get(url_str)
-                .send()
-                .and_then(|response| response.json())
-                .await
-                .map_err(PSError::Reqwest);
-
-            match result {
+        // Kept in the order they arrived so that replayed scrapes make the same decisions.
+        let mut nodes: Vec<RawNode> = Vec::new();
+        let urls = std::mem::take(&mut self.urls);
+        let mut new_urls: Vec<Url> = Vec::with_capacity(urls.len());
+        for url in urls.iter() {
+            let url_str = url.as_str();
+            match self.fetch(url).await {
                 Ok(scraped) => {
                     for val in scraped.data.iter() {
                         debug!("{:?}", val);
                     }
                     info!("Scraped {} nodes from {}.", scraped.data.len(), url_str);
-                    if !scraped.data.is_empty() {
-                        new_urls.push(ScraperClient::replace_before(
-                            url_str,
-                            scraped
-                                .data
-                                .iter()
-                                // Find the lowest date-time stamp for the new "before" parameter.
-                                .min_by(|x, y| x.created_utc.cmp(&y.created_utc))
-                                .unwrap() // Okay to unwrap because we're comparing two u64
-                                .created_utc, // ...and finally select the actual stamp
-                        )?);
-                        nodes.extend(scraped.data.into_iter());
+                    self.record_progress(url, &scraped.data, scraped.metadata.as_ref());
+                    self.oldest = scraped
+                        .data
+                        .iter()
+                        .map(|node| node.created_utc)
+                        .chain(self.oldest)
+                        .min();
+                    if let Some(progress) = self.progress.get(&ScraperClient::progress_key(url)) {
+                        for (subreddit, count) in progress.per_subreddit.iter() {
+                            info!("\t{} nodes so far from r/{}.", count, subreddit);
+                        }
+                    }
+                    self.failures.remove(&ScraperClient::progress_key(url));
+                    if let Some(next) = ScraperClient::next_page(url, &scraped.data)? {
+                        new_urls.push(next);
+                        nodes.extend(scraped.data);
                     } else {
                         // We shouldn't raise an error here because we may have more URLs to check.
                         // Zero nodes may not be an error for a particular URL.
                         info!("No more nodes in: {}", url_str);
+                        self.observers.emit(|| ScrapeEvent::CursorExhausted {
+                            url: url_str.to_string(),
+                        });
+                        self.exhausted += 1;
                     }
                 }
                 // Any actual errors are reported, but we continue scraping instead of failing to
-                // be safe.
-                Err(error) => error!("{} @ {}", error, url_str),
+                // be safe. The query is retried next round with the same cursor until it fails
+                // DEFAULT_THRESH times in a row. Errors that can't succeed on retry give up at once.
+                Err(error) => {
+                    self.report_error(url, &error);
+                    let failures = self
+                        .failures
+                        .entry(ScraperClient::progress_key(url))
+                        .or_insert(0);
+                    *failures = if error.is_retryable() {
+                        *failures + 1
+                    } else {
+                        DEFAULT_THRESH
+                    };
+                    if *failures >= DEFAULT_THRESH {
+                        warn!("Giving up on {} after {} failures.", url_str, failures);
+                        self.failed += 1;
+                    } else {
+                        new_urls.push(url.clone());
+                    }
+                }
             }
             self.backoff().await;
         }
-        // Replace the old URLs with the new URLs with the new "before" query pairs.
-        // This is messy and I don't like it. :(
-        self.urls.clear();
-        self.urls.extend(new_urls.into_iter());
+        // Replace the old URLs with the new URLs with the new cursor query pairs.
+        self.urls = new_urls;
+        let mut seen = HashSet::new();
+        nodes.retain(|raw| seen.insert(raw.clone()));
         // Add newly scraped nodes to our main list of nodes and remove junk nodes.
-        self.nodes.extend(nodes.iter().map(|node| node.into()));
-        self.filter_junk();
+        let mut counts = if self.quotas.is_some() {
+            self.subreddit_counts()
+        } else {
+            HashMap::new()
+        };
+        for node in nodes.iter() {
+            if !self.passes_filters(node) {
+                continue;
+            }
+            let subreddit = node.subreddit().to_lowercase();
+            if let Some(quotas) = &self.quotas {
+                if !quotas.accepts(&subreddit, &counts) {
+                    self.observers
+                        .emit(|| ScrapeEvent::NodeRejected { filter: "quota" });
+                    continue;
+                }
+            }
+            if self.insert_node(node, node.into()) {
+                *counts.entry(subreddit).or_insert(0) += 1;
+            }
+        }
+        self.drop_retracted();
+        self.retire_met_quotas()?;
         Ok(nodes.len())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{scraperclient::cache::CacheMode, testdir::TestDir};
+    use std::{
+        io::{BufRead, BufReader, Write},
+        net::TcpListener,
+        sync::{atomic::AtomicUsize, Arc},
+    };
+
+    fn raw(author: &str, created_utc: u64, subreddit: &str) -> serde_json::Value {
+        serde_json::json!({
+            "author": author,
+            "created_utc": created_utc,
+            "permalink": format!("/r/{}/comments/a/t/{}/", subreddit, author),
+            "subreddit": subreddit,
+        })
+    }
+
+    // Records a page of `data` as the response to `url`.
+    fn record(directory: &Path, url: &str, data: serde_json::Value) -> Url {
+        let url = Url::parse(url).unwrap();
+        let page = serde_json::json!({ "data": data });
+        ResponseCache::new(directory, CacheMode::Record)
+            .put(&url, page.to_string().as_bytes())
+            .unwrap();
+        url
+    }
+
+    // A local server that answers every request with 503 along with the number of requests
+    // it received.
+    fn unavailable_server(subreddit: &str) -> (Url, Arc<AtomicUsize>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = Url::parse(&format!(
+            "http://{}/reddit/comment/search?subreddit={}",
+            listener.local_addr().unwrap(),
+            subreddit
+        ))
+        .unwrap();
+        let requests = Arc::new(AtomicUsize::new(0));
+        let received = Arc::clone(&requests);
+        std::thread::spawn(move || {
+            for mut connection in listener.incoming().flatten() {
+                let mut reader = BufReader::new(&connection);
+                let mut line = String::new();
+                while reader.read_line(&mut line).is_ok_and(|read| read > 2) {
+                    line.clear();
+                }
+                received.fetch_add(1, Ordering::SeqCst);
+                let _ = connection.write_all(
+                    b"HTTP/1.1 503 Service Unavailable\r\ncontent-length: 0\r\nconnection: close\r\n\r\n",
+                );
+            }
+        });
+        (url, requests)
+    }
+
+    async fn replay_until(
+        directory: &Path,
+        urls: &[Url],
+        stop: &str,
+    ) -> (ScrapeOutcome, ScraperClient) {
+        let mut scraper = ScraperClient::new(1, urls).unwrap();
+        scraper.set_cache(ResponseCache::new(directory, CacheMode::Replay));
+        let outcome = scraper.scrape_until(&stop.parse().unwrap()).await.unwrap();
+        (outcome, scraper)
+    }
+
+    #[test]
+    fn splits_batched_progress_per_subreddit() {
+        let url =
+            Url::parse("https://api.pushshift.io/reddit/comment/search?subreddit=Gaming,games")
+                .unwrap();
+        let page: Vec<RawNode> = serde_json::from_value(serde_json::json!([
+            raw("link", 3, "gaming"),
+            raw("zelda", 2, "Gaming"),
+            raw("samus", 1, "games"),
+        ]))
+        .unwrap();
+        let mut scraper = ScraperClient::new(1, std::slice::from_ref(&url)).unwrap();
+        scraper.record_progress(&url, &page, None);
+        scraper.record_progress(&url, &page[2..], None);
+
+        let progress = &scraper.view_progress()["Gaming,games"];
+        assert_eq!(progress.scraped, 4);
+        assert_eq!(progress.per_subreddit["gaming"], 2);
+        assert_eq!(progress.per_subreddit["games"], 2);
+    }
+
+    #[test]
+    fn estimates_remaining_requests() {
+        let url = |subreddit: &str| {
+            Url::parse(&format!(
+                "https://api.pushshift.io/reddit/comment/search?subreddit={}&size=100",
+                subreddit
+            ))
+            .unwrap()
+        };
+        let page: Vec<RawNode> =
+            serde_json::from_value(serde_json::json!([raw("link", 1, "gaming")])).unwrap();
+        let total = |total| PushshiftMetadata {
+            total_results: Some(total),
+            ..Default::default()
+        };
+        let mut scraper = ScraperClient::new(1, &[]).unwrap();
+        scraper.set_backoff(2);
+        scraper.record_progress(&url("gaming"), &page, Some(&total(251)));
+        scraper.record_progress(&url("games"), &page, Some(&total(101)));
+        // Queries without a total don't count and don't forget an earlier one.
+        scraper.record_progress(&url("games"), &page, Some(&PushshiftMetadata::default()));
+        scraper.record_progress(&url("zelda"), &page, None);
+
+        assert_eq!(
+            scraper.estimate_remaining(),
+            (3 + 1, Duration::from_secs(4 * 2))
+        );
+    }
+
+    #[tokio::test]
+    async fn builds_edges_from_aggregations() {
+        let directory = TestDir::new("aggs");
+        let url = PushshiftBuilder::new(PSEndpoint::Comment)
+            .author(&["link"])
+            .and_then(|builder| builder.aggs(Aggregation::Subreddit))
+            .and_then(|builder| builder.min_doc_count(1))
+            .and_then(|builder| builder.size(0))
+            .and_then(|builder| builder.build())
+            .unwrap();
+        let page = serde_json::json!({"aggs": {"subreddit": [
+            {"doc_count": 47, "key": "gaming"},
+            {"doc_count": 3, "key": "zelda"},
+            {"doc_count": 1, "key": 1546300800},
+        ]}, "data": []});
+        ResponseCache::new(directory.path(), CacheMode::Record)
+            .put(&url, page.to_string().as_bytes())
+            .unwrap();
+
+        // The hashed author, e.g. loaded to resume a hashed scrape, isn't looked up.
+        let nodes: Vec<RawNode> = serde_json::from_value(serde_json::json!([
+            raw("link", 1, "gaming"),
+            raw(&NameHasher::new(None).hash(b"zelda"), 2, "gaming")
+        ]))
+        .unwrap();
+        let mut scraper =
+            ScraperClient::from_nodes(1, &[], nodes.iter().map(Node::from).collect()).unwrap();
+        scraper.set_cache(ResponseCache::new(directory.path(), CacheMode::Replay));
+        scraper
+            .scrape_author_subreddits(PSEndpoint::Comment, 1)
+            .await
+            .unwrap();
+        assert_eq!(scraper.requests(), 1);
+        let edges: Vec<_> = scraper
+            .view_edges()
+            .iter()
+            .map(|edge| (edge.author.as_str(), edge.subreddit.as_str(), edge.weight))
+            .collect();
+        assert_eq!(edges, vec![("link", "gaming", 47), ("link", "zelda", 3)]);
+    }
+
+    #[tokio::test]
+    async fn scrapes_from_recorded_responses() {
+        let directory = TestDir::new("replay");
+        let url =
+            Url::parse("https://api.pushshift.io/reddit/comment/search?subreddit=gaming").unwrap();
+        let page =
+            serde_json::json!({"data": [raw("link", 200, "gaming"), raw("zelda", 100, "gaming")]});
+        ResponseCache::new(directory.path(), CacheMode::Record)
+            .put(&url, page.to_string().as_bytes())
+            .unwrap();
+
+        // The next page wasn't recorded so replaying gives up on the query instead of
+        // reaching the API.
+        let mut scraper = ScraperClient::new(1, &[url]).unwrap();
+        scraper
+            .set_cache(ResponseCache::new(directory.path(), CacheMode::Replay))
+            .keep_raw(Vec::new());
+        assert_eq!(scraper.scrape_nodes().await.unwrap(), 2);
+        assert_eq!(scraper.scrape_nodes().await.unwrap(), 0);
+        assert_eq!(scraper.length_nodes(), 2);
+        assert_eq!(scraper.view_raw().len(), 2);
+        assert_eq!(scraper.requests(), 2);
+    }
+
+    #[tokio::test]
+    async fn reports_why_scrape_until_stopped() {
+        let directory = TestDir::new("until");
+        let gaming = record(
+            &directory,
+            "https://api.pushshift.io/reddit/comment/search?subreddit=gaming",
+            serde_json::json!([raw("link", 300, "gaming"), raw("zelda", 200, "gaming")]),
+        );
+        record(
+            &directory,
+            "https://api.pushshift.io/reddit/comment/search?subreddit=gaming&before=200",
+            serde_json::json!([]),
+        );
+        // Never recorded so replaying fails at once.
+        let games =
+            Url::parse("https://api.pushshift.io/reddit/comment/search?subreddit=games").unwrap();
+        let gaming = std::slice::from_ref(&gaming);
+
+        for stop in ["nodes=2|requests=1", "nodes=2&requests=1"] {
+            let (outcome, scraper) = replay_until(&directory, gaming, stop).await;
+            assert_eq!(outcome, ScrapeOutcome::ReachedTarget, "{}", stop);
+            assert_eq!(scraper.requests(), 1);
+        }
+
+        let (outcome, scraper) = replay_until(&directory, gaming, "nodes=100|requests=1").await;
+        assert_eq!(outcome, ScrapeOutcome::BudgetHit);
+        assert_eq!(scraper.length_nodes(), 2);
+
+        let (outcome, scraper) = replay_until(&directory, gaming, "nodes=100").await;
+        assert_eq!(outcome, ScrapeOutcome::Exhausted);
+        assert_eq!(scraper.requests(), 2);
+
+        let (outcome, scraper) =
+            replay_until(&directory, std::slice::from_ref(&games), "nodes=100").await;
+        assert_eq!(outcome, ScrapeOutcome::Aborted);
+        assert_eq!((scraper.exhausted, scraper.failed), (0, 1));
+
+        // A failed query doesn't abort the scrape as long as another one ran its course.
+        let urls = [gaming[0].clone(), games];
+        let (outcome, scraper) = replay_until(&directory, &urls, "nodes=100").await;
+        assert_eq!(outcome, ScrapeOutcome::Exhausted);
+        assert_eq!((scraper.exhausted, scraper.failed), (1, 1));
+        assert_eq!(scraper.requests(), 3);
+    }
+
+    #[tokio::test]
+    async fn aborts_when_pages_stop_adding_nodes() {
+        let directory = TestDir::new("stalled");
+        // Every item is as old as the cursor so the next page is the same page.
+        let url = record(
+            &directory,
+            "https://api.pushshift.io/reddit/comment/search?subreddit=gaming&before=200",
+            serde_json::json!([raw("link", 200, "gaming"), raw("zelda", 200, "gaming")]),
+        );
+        let (outcome, scraper) = replay_until(&directory, &[url], "nodes=100").await;
+        assert_eq!(outcome, ScrapeOutcome::Aborted);
+        assert_eq!(scraper.length_nodes(), 2);
+        assert_eq!(scraper.requests(), 1 + DEFAULT_THRESH as u64);
+    }
+
+    #[tokio::test]
+    async fn drops_queries_that_keep_failing() {
+        let directory = TestDir::new("failing");
+        let gaming = "https://api.pushshift.io/reddit/comment/search?subreddit=gaming";
+        let pages = [("", 300), ("&before=300", 250), ("&before=250", 200)];
+        for (cursor, created_utc) in pages {
+            record(
+                &directory,
+                &format!("{}{}", gaming, cursor),
+                serde_json::json!([raw("link", created_utc, "gaming")]),
+            );
+        }
+        record(
+            &directory,
+            &format!("{}&before=200", gaming),
+            serde_json::json!([]),
+        );
+        let (games, received) = unavailable_server("games");
+
+        // Recorded pages are served while the unavailable query reaches the local server.
+        let mut scraper = ScraperClient::new(5, &[Url::parse(gaming).unwrap(), games]).unwrap();
+        scraper
+            .set_backoff(0)
+            .set_cache(ResponseCache::new(directory.path(), CacheMode::Record));
+        let outcome = scraper
+            .scrape_until(&"nodes=100".parse().unwrap())
+            .await
+            .unwrap();
+
+        // The failing query is retried each round until it fails DEFAULT_THRESH times in a row.
+        assert_eq!(outcome, ScrapeOutcome::Exhausted);
+        assert_eq!(received.load(Ordering::SeqCst), DEFAULT_THRESH as usize);
+        assert_eq!((scraper.exhausted, scraper.failed), (1, 1));
+        assert_eq!(scraper.length_nodes(), 3);
+        assert_eq!(scraper.requests(), 4 + DEFAULT_THRESH as u64);
+    }
+
+    #[test]
+    fn grows_backoff_up_to_a_minute() {
+        let backoffs: Vec<_> = [0, 1, 2, 3, 7, 8, DEFAULT_BACKOFF, u64::MAX]
+            .into_iter()
+            .map(grow_backoff)
+            .collect();
+        assert_eq!(backoffs, vec![4, 4, 4, 9, 49, 60, 60, 60]);
+    }
+
+    #[test]
+    fn retries_streams_until_the_threshold() {
+        let mut state = StreamState {
+            url: None,
+            page: VecDeque::new(),
+            backoff: None,
+            failures: 0,
+        };
+        let unavailable = PSError::Status(String::from("https://api.pushshift.io"), 503);
+        assert!(state.retry(&unavailable, 2));
+        assert_eq!(state.backoff, Some(4));
+        assert!(state.retry(&unavailable, 2));
+        assert_eq!(state.backoff, Some(16));
+        assert!(!state.retry(&unavailable, 2));
+        assert_eq!(state.failures, DEFAULT_THRESH);
+
+        // Errors that can't succeed later give up on the first failure.
+        state.failures = 0;
+        let missing = PSError::Status(String::from("https://api.pushshift.io"), 404);
+        assert!(!state.retry(&missing, 2));
+        assert_eq!(state.failures, 1);
+    }
+
+    #[tokio::test]
+    async fn streams_recorded_pages() {
+        let directory = TestDir::new("stream");
+        let gaming = "https://api.pushshift.io/reddit/comment/search?subreddit=gaming";
+        let first = record(
+            &directory,
+            gaming,
+            serde_json::json!([raw("link", 300, "gaming"), raw("zelda", 200, "gaming")]),
+        );
+        record(
+            &directory,
+            &format!("{}&before=200", gaming),
+            serde_json::json!([raw("samus", 150, "gaming")]),
+        );
+        record(
+            &directory,
+            &format!("{}&before=150", gaming),
+            serde_json::json!([]),
+        );
+        // Every item is as old as the cursor so the next page would be the same page.
+        let stalled = record(
+            &directory,
+            &format!("{}&before=100", gaming),
+            serde_json::json!([raw("kirby", 100, "gaming")]),
+        );
+        // The page after this one was never recorded.
+        let unrecorded = record(
+            &directory,
+            "https://api.pushshift.io/reddit/comment/search?subreddit=games",
+            serde_json::json!([raw("mario", 50, "games")]),
+        );
+
+        let mut scraper = ScraperClient::new(1, &[]).unwrap();
+        scraper
+            .set_backoff(0)
+            .set_cache(ResponseCache::new(directory.path(), CacheMode::Replay));
+        let authors = |items: Vec<Result<RawNode, PSError>>| -> Vec<Result<String, PSError>> {
+            items
+                .into_iter()
+                .map(|item| item.map(|raw| raw.author().to_string()))
+                .collect()
+        };
+
+        // The cursor moves to the oldest item of each page until a page comes back empty.
+        let items = authors(scraper.stream(&first).collect().await);
+        assert_eq!(
+            items.into_iter().collect::<Result<Vec<_>, _>>().unwrap(),
+            vec!["link", "zelda", "samus"]
+        );
+        assert_eq!(scraper.requests(), 3);
+
+        let items = authors(scraper.stream(&stalled).collect().await);
+        assert_eq!(
+            items.into_iter().collect::<Result<Vec<_>, _>>().unwrap(),
+            vec!["kirby"]
+        );
+        assert_eq!(scraper.requests(), 4);
+
+        // Replaying a page that wasn't recorded can't succeed later so the stream ends at once.
+        let mut items = authors(scraper.stream(&unrecorded).collect().await).into_iter();
+        assert_eq!(items.next().unwrap().unwrap(), "mario");
+        assert!(matches!(items.next(), Some(Err(PSError::NotCached(_)))));
+        assert!(items.next().is_none());
+        assert_eq!(scraper.requests(), 6);
+    }
+
+    #[tokio::test]
+    async fn resumed_strata_count_loaded_nodes() {
+        let directory = TestDir::new("strata");
+        let template = PushshiftBuilder::new(PSEndpoint::Comment);
+        let url = template
+            .clone()
+            .subreddit("gaming")
+            .and_then(|builder| builder.window(100, 300))
+            .and_then(|builder| builder.build())
+            .unwrap();
+        record(
+            &directory,
+            url.as_str(),
+            serde_json::json!([
+                raw("link", 250, "gaming"),
+                raw("zelda", 200, "gaming"),
+                raw("samus", 150, "gaming")
+            ]),
+        );
+
+        // Loaded from a file written before nodes recorded their stratum.
+        let loaded: Vec<RawNode> =
+            serde_json::from_value(serde_json::json!([raw("link", 250, "gaming")])).unwrap();
+        let mut scraper =
+            ScraperClient::from_nodes(1, &[], loaded.iter().map(Node::from).collect()).unwrap();
+        scraper.set_cache(ResponseCache::new(directory.path(), CacheMode::Replay));
+        let outcome = scraper
+            .scrape_stratified(&template, &["gaming"], 100, 300, 1, 2, Allocation::Equal)
+            .await
+            .unwrap();
+
+        assert_eq!(outcome, ScrapeOutcome::ReachedTarget);
+        assert_eq!(scraper.view_strata()[0].scraped, 2);
+        let mut strata: Vec<_> = scraper
+            .view_nodes()
+            .iter()
+            .map(|node| (node.author.as_str(), node.stratum))
+            .collect();
+        strata.sort_unstable();
+        assert_eq!(strata, vec![("link", None), ("zelda", Some(0))]);
+    }
+}
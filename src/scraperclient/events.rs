@@ -0,0 +1,121 @@
+use std::{fmt::Debug, sync::Arc, time::Duration};
+use tokio::sync::mpsc::UnboundedSender;
+
+/// Something that happened during a scrape. Events are emitted in the order they happen.
+#[derive(Clone, Debug, PartialEq)]
+pub enum ScrapeEvent {
+    RequestStarted {
+        url: String,
+    },
+    /// A response arrived or the request failed. `status` is missing if no response arrived.
+    RequestFinished {
+        url: String,
+        status: Option<u16>,
+        elapsed: Duration,
+    },
+    /// A response was parsed into items.
+    PageParsed {
+        url: String,
+        items: usize,
+        /// Items left in the query according to the response's metadata.
+        remaining: Option<u64>,
+    },
+    /// A node was added. `total` counts every node the scraper holds.
+    NodeAccepted {
+        subreddit: String,
+        total: usize,
+    },
+    /// An item was dropped by the named filter or quota.
+    NodeRejected {
+        filter: &'static str,
+    },
+    BackoffStarted {
+        seconds: u64,
+    },
+    /// A query ran out of items.
+    CursorExhausted {
+        url: String,
+    },
+    /// A request or page failed. Retryable errors may be followed by another attempt.
+    Error {
+        url: String,
+        message: String,
+        retryable: bool,
+    },
+}
+
+/// Receives the events of a scrape. Observers are called synchronously from the scraping task
+/// so they should return quickly; forward events through a channel for anything slow.
+///
+/// Closures taking `&ScrapeEvent` and unbounded Tokio senders are observers.
+pub trait ScrapeObserver: Send + Sync {
+    fn on_event(&self, event: &ScrapeEvent);
+}
+
+impl<F> ScrapeObserver for F
+where
+    F: Fn(&ScrapeEvent) + Send + Sync,
+{
+    fn on_event(&self, event: &ScrapeEvent) {
+        self(event)
+    }
+}
+
+// A dropped receiver only means that nobody listens anymore.
+impl ScrapeObserver for UnboundedSender<ScrapeEvent> {
+    fn on_event(&self, event: &ScrapeEvent) {
+        let _ = self.send(event.clone());
+    }
+}
+
+/// Observers attached to a ScraperClient.
+#[derive(Clone, Default)]
+pub struct Observers(Vec<Arc<dyn ScrapeObserver>>);
+
+impl Observers {
+    pub fn push<O: ScrapeObserver + 'static>(&mut self, observer: O) {
+        self.0.push(Arc::new(observer));
+    }
+
+    /// Builds the event only if anyone is listening.
+    pub fn emit<F: FnOnce() -> ScrapeEvent>(&self, event: F) {
+        if self.0.is_empty() {
+            return;
+        }
+        let event = event();
+        for observer in self.0.iter() {
+            observer.on_event(&event);
+        }
+    }
+}
+
+impl Debug for Observers {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Observers({})", self.0.len())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+
+    #[test]
+    fn forwards_events() {
+        let (sender, mut receiver) = tokio::sync::mpsc::unbounded_channel();
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        let mut observers = Observers::default();
+        observers.emit(|| unreachable!("Events aren't built without observers"));
+
+        observers.push(sender);
+        let log = Arc::clone(&seen);
+        observers.push(move |event: &ScrapeEvent| log.lock().unwrap().push(event.clone()));
+        observers.emit(|| ScrapeEvent::BackoffStarted { seconds: 5 });
+
+        assert_eq!(
+            receiver.try_recv().unwrap(),
+            ScrapeEvent::BackoffStarted { seconds: 5 }
+        );
+        assert_eq!(seen.lock().unwrap().len(), 1);
+    }
+}
@@ -0,0 +1,139 @@
+use ring::{
+    digest::{Context, Digest, SHA256},
+    hmac,
+};
+use std::{
+    fs,
+    io::ErrorKind,
+    path::{Path, PathBuf},
+};
+
+use super::nodestructs::{Edge, Node};
+use crate::pushshift::PSError;
+
+static TOPIC_POS: usize = 5;
+// Hashed to tell keys apart without revealing them.
+static FINGERPRINT_INPUT: &[u8] = b"thesis_gamer_scraper fingerprint";
+
+/// Hashes names of posters/topics for privacy.
+///
+/// Names are hashed with HMAC-SHA256 if a key is given so that they can't be recovered by hashing
+/// candidate usernames. Otherwise they're hashed with plain SHA256.
+pub struct NameHasher {
+    key: Option<hmac::Key>,
+}
+
+impl NameHasher {
+    pub fn new(key: Option<&[u8]>) -> Self {
+        NameHasher {
+            key: key.map(|key| hmac::Key::new(hmac::HMAC_SHA256, key)),
+        }
+    }
+
+    // Encode bytes with SHA256 using Ring
+    fn sha256(data: &[u8]) -> Digest {
+        let mut context = Context::new(&SHA256);
+        context.update(data);
+        context.finish()
+    }
+
+    /// HMAC-SHA256 if the hasher has a key or SHA256 otherwise.
+    pub fn algorithm(&self) -> &'static str {
+        match self.key {
+            Some(_) => "HMAC-SHA256",
+            None => "SHA256",
+        }
+    }
+
+    pub fn hash(&self, data: &[u8]) -> String {
+        match &self.key {
+            Some(key) => hex::encode(hmac::sign(key, data)),
+            None => hex::encode(Self::sha256(data)),
+        }
+    }
+
+    /// Hashes the author and replaces the permalink with the hash of its topic.
+    /// Topics are extracted from the _permalink_ field so nodes that are already hashed are
+    /// returned as they are.
+    pub fn hash_node(&self, node: Node) -> Node {
+        if node.is_hashed() {
+            return node;
+        }
+        Node {
+            author: self.hash(node.author.as_bytes()),
+            permalink: node
+                .permalink
+                .split('/')
+                .nth(TOPIC_POS)
+                .map_or(String::from("NA"), |topic| self.hash(topic.as_bytes())),
+            ..node
+        }
+    }
+
+    pub fn hash_edge(&self, edge: &mut Edge) {
+        edge.author = self.hash(edge.author.as_bytes());
+    }
+
+    /// Identifies the key without revealing it. Files hashed with the same key, or without one,
+    /// have the same fingerprint.
+    pub fn fingerprint(&self) -> String {
+        self.hash(FINGERPRINT_INPUT)
+    }
+
+    /// Records the fingerprint next to a node file hashed by this hasher.
+    pub fn write_fingerprint<P: AsRef<Path>>(&self, nodes: P) -> Result<(), PSError> {
+        let path = fingerprint_path(nodes);
+        fs::write(&path, self.fingerprint()).map_err(PSError::writing(&path))
+    }
+}
+
+/// Whether `name` was hashed by a NameHasher, i.e. is a hex encoded SHA256 digest. Reddit
+/// names are at most 20 characters so they can't be mistaken for one.
+pub fn is_hashed(name: &str) -> bool {
+    name.len() == 64 && name.bytes().all(|byte| byte.is_ascii_hexdigit())
+}
+
+/// Where the fingerprint of a hashed node file is stored.
+pub fn fingerprint_path<P: AsRef<Path>>(nodes: P) -> PathBuf {
+    nodes.as_ref().with_extension("hash")
+}
+
+/// Fingerprint of the key a node file was hashed with if it was recorded.
+pub fn read_fingerprint<P: AsRef<Path>>(nodes: P) -> Result<Option<String>, PSError> {
+    let path = fingerprint_path(nodes);
+    match fs::read_to_string(&path) {
+        Ok(fingerprint) => Ok(Some(fingerprint.trim().to_string())),
+        Err(error) if error.kind() == ErrorKind::NotFound => Ok(None),
+        Err(error) => Err(PSError::reading(&path)(error)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keyed_hashes_differ() {
+        let node = Node {
+            author: "spez".to_string(),
+            created_utc: 0,
+            permalink: "/r/gaming/comments/abc123/title/def456/".to_string(),
+            subreddit: "gaming".to_string(),
+            stratum: None,
+        };
+
+        let plain = NameHasher::new(None).hash_node(node.clone());
+        assert_eq!(plain.author, NameHasher::new(None).hash(b"spez"));
+        assert_eq!(plain.permalink, NameHasher::new(None).hash(b"title"));
+
+        let keyed = NameHasher::new(Some(b"secret")).hash_node(node);
+        assert_ne!(keyed.author, plain.author);
+        // Hashing twice would hash the hash and lose the topic.
+        assert_eq!(NameHasher::new(None).hash_node(plain.clone()), plain);
+        assert!(plain.is_hashed() && keyed.is_hashed());
+        assert_ne!(
+            NameHasher::new(Some(b"secret")).fingerprint(),
+            NameHasher::new(Some(b"other")).fingerprint()
+        );
+    }
+}
@@ -0,0 +1,258 @@
+use regex::{Regex, RegexBuilder};
+use std::fmt::Debug;
+
+use super::{bots::BotFilter, nodestructs::RawNode};
+use crate::pushshift::{Comparison, PSError};
+
+/// A check applied to each item as it arrives.
+pub trait NodeFilter: Debug + Send + Sync {
+    /// Short name used when reporting rejections.
+    fn name(&self) -> &'static str;
+
+    /// Whether to keep the item. Filters may learn from every item they see.
+    fn accepts(&mut self, raw: &RawNode) -> bool;
+
+    /// Fields the filter needs in addition to those of a Node.
+    fn fields(&self) -> Vec<&'static str> {
+        Vec::new()
+    }
+}
+
+/// Keeps items whose score matches. Items without a score are dropped.
+#[derive(Clone, Copy, Debug)]
+pub struct ScoreFilter(pub Comparison);
+
+impl NodeFilter for ScoreFilter {
+    fn name(&self) -> &'static str {
+        "score"
+    }
+
+    fn accepts(&mut self, raw: &RawNode) -> bool {
+        raw.score()
+            .is_some_and(|score| self.0.matches(score.into()))
+    }
+
+    fn fields(&self) -> Vec<&'static str> {
+        vec!["score"]
+    }
+}
+
+/// Keeps items created in `[after, before)`.
+#[derive(Clone, Copy, Debug)]
+pub struct TimeFilter {
+    pub after: Option<u64>,
+    pub before: Option<u64>,
+}
+
+impl NodeFilter for TimeFilter {
+    fn name(&self) -> &'static str {
+        "time"
+    }
+
+    fn accepts(&mut self, raw: &RawNode) -> bool {
+        self.after.is_none_or(|after| raw.created_utc >= after)
+            && self.before.is_none_or(|before| raw.created_utc < before)
+    }
+}
+
+/// Keeps items whose body length in characters matches. Items without a body, such as
+/// submissions, are kept.
+#[derive(Clone, Copy, Debug)]
+pub struct BodyLengthFilter(pub Comparison);
+
+impl NodeFilter for BodyLengthFilter {
+    fn name(&self) -> &'static str {
+        "body_length"
+    }
+
+    fn accepts(&mut self, raw: &RawNode) -> bool {
+        raw.body()
+            .is_none_or(|body| self.0.matches(body.chars().count() as i64))
+    }
+
+    fn fields(&self) -> Vec<&'static str> {
+        vec!["body"]
+    }
+}
+
+/// Drops items whose body was removed by moderators or deleted by its author.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct RemovedFilter;
+
+impl NodeFilter for RemovedFilter {
+    fn name(&self) -> &'static str {
+        "removed"
+    }
+
+    fn accepts(&mut self, raw: &RawNode) -> bool {
+        !matches!(raw.body().map(str::trim), Some("[removed]" | "[deleted]"))
+    }
+
+    fn fields(&self) -> Vec<&'static str> {
+        vec!["body"]
+    }
+}
+
+/// Drops items marked NSFW. Only submissions carry the mark.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct NsfwFilter;
+
+impl NodeFilter for NsfwFilter {
+    fn name(&self) -> &'static str {
+        "nsfw"
+    }
+
+    fn accepts(&mut self, raw: &RawNode) -> bool {
+        raw.over_18() != Some(true)
+    }
+
+    fn fields(&self) -> Vec<&'static str> {
+        vec!["over_18"]
+    }
+}
+
+/// Keeps items whose body or title matches a case insensitive regular expression.
+#[derive(Clone, Debug)]
+pub struct KeywordFilter(Regex);
+
+impl KeywordFilter {
+    pub fn new(pattern: &str) -> Result<Self, PSError> {
+        RegexBuilder::new(pattern)
+            .case_insensitive(true)
+            .build()
+            .map(KeywordFilter)
+            .map_err(|_| PSError::InvalidPattern(pattern.to_string()))
+    }
+}
+
+impl NodeFilter for KeywordFilter {
+    fn name(&self) -> &'static str {
+        "keyword"
+    }
+
+    fn accepts(&mut self, raw: &RawNode) -> bool {
+        [raw.body(), raw.title()]
+            .into_iter()
+            .flatten()
+            .any(|text| self.0.is_match(text))
+    }
+
+    fn fields(&self) -> Vec<&'static str> {
+        vec!["body", "title"]
+    }
+}
+
+/// Runs the bot filter followed by any other filters in the order they were added. An item is
+/// counted against the first filter that rejects it.
+#[derive(Debug)]
+pub struct FilterPipeline {
+    bots: BotFilter,
+    filters: Vec<Box<dyn NodeFilter>>,
+    // Rejections per filter with the bot filter first
+    rejected: Vec<usize>,
+}
+
+impl Default for FilterPipeline {
+    fn default() -> Self {
+        FilterPipeline::new(BotFilter::new())
+    }
+}
+
+impl FilterPipeline {
+    pub fn new(bots: BotFilter) -> Self {
+        FilterPipeline {
+            bots,
+            filters: Vec::new(),
+            rejected: vec![0],
+        }
+    }
+
+    pub fn push<F: NodeFilter + 'static>(&mut self, filter: F) -> &mut Self {
+        self.filters.push(Box::new(filter));
+        self.rejected.push(0);
+        self
+    }
+
+    pub fn accepts(&mut self, raw: &RawNode) -> bool {
+        self.rejection(raw).is_none()
+    }
+
+    /// Name of the filter that rejects the item or None if every filter accepts it.
+    pub fn rejection(&mut self, raw: &RawNode) -> Option<&'static str> {
+        if !self.bots.accepts(raw) {
+            self.rejected[0] += 1;
+            return Some(self.bots.name());
+        }
+        let index = self
+            .filters
+            .iter_mut()
+            .position(|filter| !filter.accepts(raw))?;
+        self.rejected[index + 1] += 1;
+        Some(self.filters[index].name())
+    }
+
+    /// Fields every filter needs in addition to those of a Node.
+    pub fn fields(&self) -> Vec<&'static str> {
+        let mut fields: Vec<_> = std::iter::once(&self.bots as &dyn NodeFilter)
+            .chain(self.filters.iter().map(AsRef::as_ref))
+            .flat_map(|filter| filter.fields())
+            .collect();
+        fields.sort_unstable();
+        fields.dedup();
+        fields
+    }
+
+    /// Number of items each filter rejected so far.
+    pub fn rejections(&self) -> Vec<(&'static str, usize)> {
+        std::iter::once(self.bots.name())
+            .chain(self.filters.iter().map(|filter| filter.name()))
+            .zip(self.rejected.iter().copied())
+            .collect()
+    }
+
+    pub fn bots(&self) -> &BotFilter {
+        &self.bots
+    }
+
+    pub fn bots_mut(&mut self) -> &mut BotFilter {
+        &mut self.bots
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn raw(author: &str, score: i32, body: &str) -> RawNode {
+        serde_json::from_value(serde_json::json!({
+            "author": author,
+            "body": body,
+            "created_utc": 100,
+            "permalink": "/r/gaming/comments/abc/title/def/",
+            "score": score,
+            "subreddit": "gaming",
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn counts_the_first_rejection() {
+        let mut pipeline = FilterPipeline::default();
+        assert!(!pipeline.accepts(&raw("[deleted]", 1, "")));
+        pipeline
+            .push(ScoreFilter(">0".parse().unwrap()))
+            .push(RemovedFilter)
+            .push(KeywordFilter::new("zelda").unwrap());
+
+        assert!(pipeline.accepts(&raw("link", 5, "Zelda rocks")));
+        assert!(!pipeline.accepts(&raw("AutoModerator", 5, "zelda")));
+        assert!(!pipeline.accepts(&raw("ganon", -1, "[removed]")));
+        assert!(!pipeline.accepts(&raw("ganon", 1, "[removed]")));
+        assert!(!pipeline.accepts(&raw("ganon", 1, "mario")));
+        assert_eq!(
+            pipeline.rejections(),
+            vec![("bots", 2), ("score", 1), ("removed", 1), ("keyword", 1)]
+        );
+        assert_eq!(pipeline.fields(), vec!["body", "score", "title"]);
+    }
+}
@@ -1,5 +1,12 @@
 use serde::{Deserialize, Serialize};
-use std::convert::From;
+use std::{
+    cmp::Ordering,
+    collections::HashMap,
+    convert::From,
+    hash::{Hash, Hasher},
+};
+
+use super::hashing::is_hashed;
 
 // Root data type for scraping from https://reddit.com/{user}.json
 #[derive(Debug, Deserialize)]
@@ -17,27 +24,177 @@ pub struct RedditUserBase {
 #[derive(Debug, Deserialize)]
 pub struct PushshiftBase {
     pub data: Vec<RawNode>,
+    /// Buckets keyed by the aggregated field if the request used `aggs`.
+    #[serde(default)]
+    pub aggs: HashMap<String, Vec<AggBucket>>,
+    #[serde(default)]
+    pub metadata: Option<PushshiftMetadata>,
+}
+
+/// Response metadata returned when the request sets `metadata=true`. Every field is optional
+/// so that incomplete metadata never costs the page it came with.
+#[derive(Clone, Debug, Default, Deserialize)]
+pub struct PushshiftMetadata {
+    /// Results matching the query including those in the current page.
+    #[serde(default)]
+    pub total_results: Option<u64>,
+    #[serde(default)]
+    pub shards: Shards,
+    #[serde(default)]
+    pub execution_time_milliseconds: f64,
+    #[serde(default)]
+    pub timed_out: bool,
+    #[serde(default)]
+    pub terminated_early: bool,
+}
+
+/// Number of Elasticsearch shards that answered the query. Failed shards mean the results are
+/// likely incomplete.
+#[derive(Clone, Debug, Default, Deserialize)]
+pub struct Shards {
+    #[serde(default)]
+    pub total: u32,
+    #[serde(default)]
+    pub successful: u32,
+    #[serde(default)]
+    pub skipped: u32,
+    #[serde(default)]
+    pub failed: u32,
+}
+
+impl PushshiftMetadata {
+    /// The API answered but may have left out results.
+    pub fn is_partial(&self) -> bool {
+        self.timed_out || self.terminated_early || self.shards.failed > 0
+    }
+
+    /// Results left after a page of `received` items if the API reported its total.
+    pub fn remaining(&self, received: usize) -> Option<u64> {
+        self.total_results
+            .map(|total| total.saturating_sub(received as u64))
+    }
+}
+
+/// Aggregation keys are strings (subreddit, author) or epochs (created_utc).
+#[derive(Clone, Debug, Deserialize, Eq, Hash, PartialEq)]
+#[serde(untagged)]
+pub enum AggKey {
+    Text(String),
+    Time(u64),
+}
+
+/// Number of results (`doc_count`) for a single value of an aggregated field.
+#[derive(Clone, Debug, Deserialize, Eq, Hash, PartialEq)]
+pub struct AggBucket {
+    pub doc_count: u64,
+    pub key: AggKey,
 }
 
 /// RawNode is a Node plus some associated metadata such as the time or comment rating.
-#[derive(Clone, Debug, Deserialize, Eq, Hash, PartialEq, Serialize)]
+#[derive(Clone, Debug, Deserialize, Eq, Hash, Ord, PartialEq, PartialOrd, Serialize)]
 pub struct RawNode {
     /// Redditor who wrote the post
     author: String,
-    body: String,
+    /// Comment text. Missing if the request's fields excluded it.
+    #[serde(default)]
+    body: Option<String>,
     /// Creation time for the post
     pub created_utc: u64,
+    /// Set to moderator or admin if the item was distinguished. Missing if the request's fields
+    /// excluded it.
+    #[serde(default)]
+    distinguished: Option<String>,
     /// Link to post.
     permalink: String,
-    /// Post rating
-    score: i32,
+    /// Post rating. Missing if the request's fields excluded it.
+    #[serde(default)]
+    score: Option<i32>,
+    /// Number of comments; only submissions have this field.
+    #[serde(default)]
+    num_comments: Option<u32>,
+    /// Marked NSFW; only submissions have this field.
+    #[serde(default)]
+    over_18: Option<bool>,
     /// Subreddit for post
     subreddit: String,
+    /// Only submissions have a title.
+    #[serde(default)]
+    title: Option<String>,
+}
+
+impl RawNode {
+    pub fn author(&self) -> &str {
+        &self.author
+    }
+
+    pub fn body(&self) -> Option<&str> {
+        self.body.as_deref()
+    }
+
+    pub fn distinguished(&self) -> Option<&str> {
+        self.distinguished.as_deref()
+    }
+
+    pub fn subreddit(&self) -> &str {
+        &self.subreddit
+    }
+
+    pub fn score(&self) -> Option<i32> {
+        self.score
+    }
+
+    pub fn num_comments(&self) -> Option<u32> {
+        self.num_comments
+    }
+
+    pub fn over_18(&self) -> Option<bool> {
+        self.over_18
+    }
+
+    pub fn title(&self) -> Option<&str> {
+        self.title.as_deref()
+    }
+}
+
+/// Determines which fields are requested from the API and thus what can be written out.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum OutputMode {
+    /// Only the fields kept by Node.
+    #[default]
+    Node,
+    /// Every field deserialized by RawNode. The items are written as they arrived in addition
+    /// to the nodes.
+    Raw,
+}
+
+impl OutputMode {
+    /// Fields for the API's `fields` parameter.
+    pub fn fields(&self) -> &'static [&'static str] {
+        match self {
+            OutputMode::Node => &["author", "created_utc", "permalink", "subreddit"],
+            OutputMode::Raw => &[
+                "author",
+                "body",
+                "created_utc",
+                "distinguished",
+                "num_comments",
+                "over_18",
+                "permalink",
+                "score",
+                "subreddit",
+                "title",
+            ],
+        }
+    }
 }
 
 // All of the members are public because of the scraper.
 /// Nodes are parsed RawNodes with only the data required for my thesis.
-#[derive(Clone, Debug, Deserialize, Eq, Hash, PartialEq, Serialize)]
+///
+/// Nodes are compared without their stratum so that the same item is only kept once however it
+/// was sampled.
+#[derive(Clone, Debug, Deserialize, Serialize)]
 pub struct Node {
     // Vertex
     pub author: String,
@@ -47,6 +204,78 @@ pub struct Node {
     pub permalink: String,
     // Main edge
     pub subreddit: String,
+    /// Bucket of the stratified sample the node was drawn from. See Stratum.
+    #[serde(default)]
+    pub stratum: Option<u32>,
+}
+
+impl Node {
+    // Every field except for the stratum.
+    fn key(&self) -> (&str, u64, &str, &str) {
+        (
+            &self.author,
+            self.created_utc,
+            &self.permalink,
+            &self.subreddit,
+        )
+    }
+}
+
+impl PartialEq for Node {
+    fn eq(&self, other: &Self) -> bool {
+        self.key() == other.key()
+    }
+}
+
+impl Eq for Node {}
+
+impl Hash for Node {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.key().hash(state);
+    }
+}
+
+impl PartialOrd for Node {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Node {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.key().cmp(&other.key())
+    }
+}
+
+impl Node {
+    /// Fullname of the item, i.e. t1_ for comments and t3_ for submissions, parsed from the
+    /// permalink. Hashed nodes don't have one since their permalink is replaced by the topic.
+    pub fn item_id(&self) -> Option<String> {
+        // /r/{subreddit}/comments/{submission}/{title}/{comment}/
+        let mut parts = self.permalink.split('/').skip(3);
+        if parts.next() != Some("comments") {
+            return None;
+        }
+        let submission = parts.next().filter(|id| !id.is_empty())?;
+        match parts.nth(1).filter(|id| !id.is_empty()) {
+            Some(comment) => Some(format!("t1_{}", comment)),
+            None => Some(format!("t3_{}", submission)),
+        }
+    }
+
+    /// Whether the names were hashed with NameHasher.
+    pub fn is_hashed(&self) -> bool {
+        is_hashed(&self.author)
+    }
+}
+
+/// Weighted author to subreddit edge built from subreddit aggregations.
+#[derive(Clone, Debug, Deserialize, Eq, Hash, PartialEq, Serialize)]
+pub struct Edge {
+    pub author: String,
+    pub subreddit: String,
+    // Number of posts by author in subreddit
+    pub weight: u64,
 }
 
 // Parse relevant information from raw nodes
@@ -57,6 +286,7 @@ impl From<RawNode> for Node {
             created_utc: raw.created_utc,
             permalink: raw.permalink,
             subreddit: raw.subreddit,
+            stratum: None,
         }
     }
 }
@@ -68,6 +298,7 @@ impl From<&RawNode> for Node {
             created_utc: raw.created_utc,
             permalink: raw.permalink.clone(),
             subreddit: raw.subreddit.clone(),
+            stratum: None,
         }
     }
 }
@@ -79,3 +310,106 @@ impl From<RedditUserRoot> for RedditUserBase {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_reduced_fields() {
+        // A page requested with OutputMode::Node's fields.
+        let page: PushshiftBase = serde_json::from_str(
+            r#"{"data": [{"author": "link", "created_utc": 100,
+                "permalink": "/r/gaming/comments/abc/title/def/", "subreddit": "gaming"}]}"#,
+        )
+        .unwrap();
+        let raw = &page.data[0];
+        assert_eq!(raw.author(), "link");
+        assert_eq!(raw.body(), None);
+        assert_eq!(raw.score(), None);
+        assert_eq!(raw.title(), None);
+        assert_eq!(Node::from(raw).subreddit, "gaming");
+
+        let full: RawNode = serde_json::from_str(
+            r#"{"author": "link", "body": "hey", "created_utc": 100, "distinguished": null,
+                "permalink": "/r/gaming/comments/abc/title/def/", "score": -2,
+                "subreddit": "gaming", "unrequested": true}"#,
+        )
+        .unwrap();
+        assert_eq!(full.body(), Some("hey"));
+        assert_eq!(full.score(), Some(-2));
+        assert_eq!(full.distinguished(), None);
+    }
+
+    #[test]
+    fn parses_partial_metadata() {
+        let page: PushshiftBase = serde_json::from_str(
+            r#"{"data": [], "metadata": {"total_results": 2500, "timed_out": false,
+                "shards": {"total": 8, "successful": 7, "skipped": 0, "failed": 1},
+                "execution_time_milliseconds": 12.5}}"#,
+        )
+        .unwrap();
+        let metadata = page.metadata.unwrap();
+        assert_eq!(metadata.remaining(1000), Some(1500));
+        assert_eq!(metadata.remaining(3000), Some(0));
+        assert!(metadata.is_partial());
+
+        // Data survives metadata that lacks the total and shard counts.
+        let page: PushshiftBase = serde_json::from_str(
+            r#"{"data": [{"author": "link", "created_utc": 100,
+                "permalink": "/r/gaming/comments/abc/title/def/", "subreddit": "gaming"}],
+                "metadata": {"shards": {"skipped": 0}}}"#,
+        )
+        .unwrap();
+        assert_eq!(page.data.len(), 1);
+        let metadata = page.metadata.unwrap();
+        assert_eq!(metadata.remaining(1), None);
+        assert!(!metadata.is_partial());
+
+        let timed_out = PushshiftMetadata {
+            timed_out: true,
+            ..Default::default()
+        };
+        assert!(timed_out.is_partial());
+    }
+
+    #[test]
+    fn parses_aggregations() {
+        let page: PushshiftBase = serde_json::from_str(
+            r#"{"aggs": {
+                "subreddit": [{"doc_count": 47, "key": "gaming"}, {"doc_count": 3, "key": "Games"}],
+                "created_utc": [{"doc_count": 50, "key": 1546300800}]
+            }, "data": []}"#,
+        )
+        .unwrap();
+        assert_eq!(
+            page.aggs["subreddit"],
+            vec![
+                AggBucket {
+                    doc_count: 47,
+                    key: AggKey::Text("gaming".into())
+                },
+                AggBucket {
+                    doc_count: 3,
+                    key: AggKey::Text("Games".into())
+                },
+            ]
+        );
+        assert_eq!(page.aggs["created_utc"][0].key, AggKey::Time(1546300800));
+        assert!(page.data.is_empty());
+    }
+
+    #[test]
+    fn compares_nodes_without_strata() {
+        let node = |stratum| Node {
+            author: "link".to_string(),
+            created_utc: 100,
+            permalink: "/r/gaming/comments/abc/title/def/".to_string(),
+            subreddit: "gaming".to_string(),
+            stratum,
+        };
+        assert_eq!(node(None), node(Some(2)));
+        let nodes: std::collections::HashSet<_> = [node(Some(1)), node(None)].into();
+        assert_eq!(nodes.len(), 1);
+    }
+}
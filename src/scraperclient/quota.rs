@@ -0,0 +1,166 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+use super::stratified::apportion;
+
+/// How the node budget is divided between subreddits.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Quota {
+    /// Every subreddit is capped at the same number of nodes.
+    Fixed(usize),
+    /// The budget is split in proportion to the number of items the API reports per subreddit.
+    Proportional,
+    /// Every subreddit is guaranteed this many nodes out of the budget. The remainder is first
+    /// come, first served.
+    Minimum(usize),
+}
+
+/// Per subreddit targets derived from a Quota. Subreddits are lowercased to match
+/// ScraperClient::subreddit_counts().
+#[derive(Clone, Debug)]
+pub struct Quotas {
+    quota: Quota,
+    budget: usize,
+    targets: HashMap<String, usize>,
+}
+
+impl Quotas {
+    /// `populations` is only used by proportional quotas. Subreddits without a population are
+    /// weighted as if they were empty.
+    pub fn new<T: AsRef<str>>(
+        quota: Quota,
+        budget: usize,
+        subs: &[T],
+        populations: &HashMap<String, u64>,
+    ) -> Self {
+        let subs: Vec<String> = subs.iter().map(|sub| sub.as_ref().to_lowercase()).collect();
+        let targets = match quota {
+            Quota::Fixed(target) | Quota::Minimum(target) => {
+                subs.into_iter().map(|sub| (sub, target)).collect()
+            }
+            Quota::Proportional => {
+                let weights: Vec<u64> = subs
+                    .iter()
+                    .map(|sub| populations.get(sub).copied().unwrap_or(0))
+                    .collect();
+                subs.into_iter().zip(apportion(budget, &weights)).collect()
+            }
+        };
+
+        Quotas {
+            quota,
+            budget,
+            targets,
+        }
+    }
+
+    pub fn target(&self, subreddit: &str) -> usize {
+        self.targets
+            .get(&subreddit.to_lowercase())
+            .copied()
+            .unwrap_or(0)
+    }
+
+    /// Whether another node from `subreddit` may be added given the current counts.
+    /// Subreddits that weren't given a quota are never accepted.
+    pub fn accepts(&self, subreddit: &str, counts: &HashMap<String, usize>) -> bool {
+        let subreddit = subreddit.to_lowercase();
+        let Some(&target) = self.targets.get(&subreddit) else {
+            return false;
+        };
+        let count = counts.get(&subreddit).copied().unwrap_or(0);
+
+        match self.quota {
+            Quota::Fixed(_) | Quota::Proportional => count < target,
+            Quota::Minimum(_) => {
+                // Nodes past the minimum can only use the part of the budget that isn't
+                // reserved for subreddits that haven't reached their minimum yet.
+                let total: usize = self
+                    .targets
+                    .keys()
+                    .map(|sub| counts.get(sub).copied().unwrap_or(0))
+                    .sum();
+                let reserved: usize = self
+                    .targets
+                    .iter()
+                    .map(|(sub, target)| {
+                        target.saturating_sub(counts.get(sub).copied().unwrap_or(0))
+                    })
+                    .sum();
+                count < target || total + reserved < self.budget
+            }
+        }
+    }
+
+    /// Every subreddit stopped accepting nodes so the scrape is done.
+    pub fn all_met(&self, counts: &HashMap<String, usize>) -> bool {
+        self.targets
+            .keys()
+            .all(|subreddit| !self.accepts(subreddit, counts))
+    }
+
+    /// Achieved and target counts per subreddit, sorted by subreddit.
+    pub fn report(&self, counts: &HashMap<String, usize>) -> Vec<(String, usize, usize)> {
+        let mut report: Vec<_> = self
+            .targets
+            .iter()
+            .map(|(subreddit, target)| {
+                (
+                    subreddit.clone(),
+                    counts.get(subreddit).copied().unwrap_or(0),
+                    *target,
+                )
+            })
+            .collect();
+        report.sort();
+        report
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn counts(counts: &[(&str, usize)]) -> HashMap<String, usize> {
+        counts
+            .iter()
+            .map(|(sub, count)| (sub.to_string(), *count))
+            .collect()
+    }
+
+    #[test]
+    fn minimum_reserves_budget() {
+        let quotas = Quotas::new(
+            Quota::Minimum(10),
+            30,
+            &["gaming", "Games"],
+            &HashMap::new(),
+        );
+
+        // r/gaming may grow past its minimum while r/games still has room for its own.
+        let current = counts(&[("gaming", 15), ("games", 4)]);
+        assert!(quotas.accepts("gaming", &current));
+        // 24 nodes plus 6 reserved for r/games exhaust the budget.
+        let current = counts(&[("gaming", 20), ("games", 4)]);
+        assert!(!quotas.accepts("gaming", &current));
+        assert!(quotas.accepts("Games", &current));
+        assert!(!quotas.all_met(&current));
+
+        let current = counts(&[("gaming", 20), ("games", 10)]);
+        assert!(quotas.all_met(&current));
+    }
+
+    #[test]
+    fn proportional_targets() {
+        let populations = counts(&[("gaming", 900), ("games", 100)])
+            .into_iter()
+            .map(|(sub, count)| (sub, count as u64))
+            .collect();
+        let quotas = Quotas::new(Quota::Proportional, 50, &["gaming", "games"], &populations);
+        assert_eq!(
+            quotas.report(&HashMap::new()),
+            vec![("games".to_string(), 0, 5), ("gaming".to_string(), 0, 45)]
+        );
+    }
+}
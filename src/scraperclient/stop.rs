@@ -0,0 +1,275 @@
+use serde::{Deserialize, Serialize};
+use std::{
+    fmt::{Display, Formatter},
+    str::FromStr,
+    time::Duration,
+};
+
+use crate::pushshift::PSError;
+
+/// Snapshot of a scrape that stop conditions are checked against.
+#[derive(Clone, Debug, Default)]
+pub struct ScrapeState {
+    pub nodes: usize,
+    pub authors: usize,
+    /// Oldest created_utc scraped during this run.
+    pub oldest: Option<u64>,
+    pub elapsed: Duration,
+    pub requests: u64,
+    /// New authors per item received in the last round. None before the first round.
+    pub discovery_rate: Option<f64>,
+}
+
+/// Why ScraperClient::scrape_until() stopped. The nodes collected so far are kept regardless.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Hash)]
+pub enum ScrapeOutcome {
+    /// The stop condition or every subreddit quota was met.
+    ReachedTarget,
+    /// Every query ran out of items before the target was reached.
+    Exhausted,
+    /// A time or request budget ran out before the target was reached.
+    BudgetHit,
+    /// Every remaining query failed repeatedly or stopped yielding new nodes.
+    Aborted,
+}
+
+/// When ScraperClient::scrape_until() stops.
+///
+/// Conditions may be parsed from expressions such as `authors=5000|elapsed=3600` where `&`
+/// (all) binds tighter than `|` (any). Valid terms are `nodes`, `authors`, `floor` (epoch),
+/// `elapsed` (seconds), `requests`, and `saturation` (new authors per item).
+/// Conditions are serialized as expressions as well.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(into = "String", try_from = "String")]
+pub enum StopCondition {
+    Nodes(usize),
+    Authors(usize),
+    /// Stops once items created at or before this epoch are reached.
+    CreatedFloor(u64),
+    Elapsed(Duration),
+    Requests(u64),
+    /// Stops once the rate of new authors per item drops below the threshold.
+    Saturation(f64),
+    All(Vec<StopCondition>),
+    Any(Vec<StopCondition>),
+}
+
+impl StopCondition {
+    pub fn is_met(&self, state: &ScrapeState) -> bool {
+        use StopCondition::*;
+        match self {
+            Nodes(nodes) => state.nodes >= *nodes,
+            Authors(authors) => state.authors >= *authors,
+            CreatedFloor(floor) => state.oldest.is_some_and(|oldest| oldest <= *floor),
+            Elapsed(elapsed) => state.elapsed >= *elapsed,
+            Requests(requests) => state.requests >= *requests,
+            Saturation(rate) => state.discovery_rate.is_some_and(|found| found < *rate),
+            All(conditions) => conditions.iter().all(|condition| condition.is_met(state)),
+            Any(conditions) => conditions.iter().any(|condition| condition.is_met(state)),
+        }
+    }
+
+    /// Whether the condition is met by its targets as opposed to its time or request budgets.
+    /// Targets win when both are met.
+    pub fn is_target_met(&self, state: &ScrapeState) -> bool {
+        use StopCondition::*;
+        match self {
+            Elapsed(_) | Requests(_) => false,
+            All(conditions) => {
+                self.is_met(state)
+                    && conditions
+                        .iter()
+                        .any(|condition| condition.is_target_met(state))
+            }
+            Any(conditions) => conditions
+                .iter()
+                .any(|condition| condition.is_target_met(state)),
+            _ => self.is_met(state),
+        }
+    }
+
+    fn parse_term(term: &str) -> Result<Self, PSError> {
+        let invalid = || PSError::InvalidStopCondition(term.to_string());
+        let (name, value) = term.split_once('=').ok_or_else(invalid)?;
+        let value = value.trim();
+
+        Ok(match name.trim() {
+            "nodes" => StopCondition::Nodes(value.parse().map_err(|_| invalid())?),
+            "authors" => StopCondition::Authors(value.parse().map_err(|_| invalid())?),
+            "floor" => StopCondition::CreatedFloor(value.parse().map_err(|_| invalid())?),
+            "elapsed" => {
+                StopCondition::Elapsed(Duration::from_secs(value.parse().map_err(|_| invalid())?))
+            }
+            "requests" => StopCondition::Requests(value.parse().map_err(|_| invalid())?),
+            "saturation" => {
+                // Rates are new authors per item so only finite, non-negative ones make sense.
+                let rate: f64 = value.parse().map_err(|_| invalid())?;
+                if !rate.is_finite() || rate < 0.0 {
+                    return Err(invalid());
+                }
+                StopCondition::Saturation(rate)
+            }
+            _ => return Err(invalid()),
+        })
+    }
+}
+
+/// Renders the condition as an expression. Only conditions that FromStr could produce, i.e.
+/// any of all of terms, round trip.
+impl Display for StopCondition {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        use StopCondition::*;
+        let join = |conditions: &[StopCondition], separator| {
+            conditions
+                .iter()
+                .map(ToString::to_string)
+                .collect::<Vec<_>>()
+                .join(separator)
+        };
+        match self {
+            Nodes(nodes) => write!(f, "nodes={}", nodes),
+            Authors(authors) => write!(f, "authors={}", authors),
+            CreatedFloor(floor) => write!(f, "floor={}", floor),
+            Elapsed(elapsed) => write!(f, "elapsed={}", elapsed.as_secs()),
+            Requests(requests) => write!(f, "requests={}", requests),
+            Saturation(rate) => write!(f, "saturation={}", rate),
+            All(conditions) => write!(f, "{}", join(conditions, "&")),
+            Any(conditions) => write!(f, "{}", join(conditions, "|")),
+        }
+    }
+}
+
+impl From<StopCondition> for String {
+    fn from(condition: StopCondition) -> Self {
+        condition.to_string()
+    }
+}
+
+impl TryFrom<String> for StopCondition {
+    type Error = PSError;
+
+    fn try_from(expression: String) -> Result<Self, Self::Error> {
+        expression.parse()
+    }
+}
+
+impl FromStr for StopCondition {
+    type Err = PSError;
+
+    fn from_str(expression: &str) -> Result<Self, Self::Err> {
+        let mut any = expression
+            .split('|')
+            .map(|all| {
+                let mut all = all
+                    .split('&')
+                    .map(StopCondition::parse_term)
+                    .collect::<Result<Vec<_>, _>>()?;
+                Ok(if all.len() == 1 {
+                    all.remove(0)
+                } else {
+                    StopCondition::All(all)
+                })
+            })
+            .collect::<Result<Vec<_>, PSError>>()?;
+
+        Ok(if any.len() == 1 {
+            any.remove(0)
+        } else {
+            StopCondition::Any(any)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_precedence() {
+        assert_eq!(
+            "authors=5000|nodes=100&saturation=0.05"
+                .parse::<StopCondition>()
+                .unwrap(),
+            StopCondition::Any(vec![
+                StopCondition::Authors(5000),
+                StopCondition::All(vec![
+                    StopCondition::Nodes(100),
+                    StopCondition::Saturation(0.05)
+                ]),
+            ])
+        );
+        assert_eq!(
+            "elapsed=60".parse::<StopCondition>().unwrap(),
+            StopCondition::Elapsed(Duration::from_secs(60))
+        );
+        assert!(matches!(
+            "authors".parse::<StopCondition>(),
+            Err(PSError::InvalidStopCondition(_))
+        ));
+        assert!(matches!(
+            "users=5".parse::<StopCondition>(),
+            Err(PSError::InvalidStopCondition(_))
+        ));
+    }
+
+    #[test]
+    fn rejects_invalid_saturation() {
+        for expression in ["saturation=NaN", "saturation=inf", "saturation=-0.1"] {
+            assert!(
+                matches!(
+                    expression.parse::<StopCondition>(),
+                    Err(PSError::InvalidStopCondition(_))
+                ),
+                "{}",
+                expression
+            );
+        }
+        assert_eq!(
+            "saturation=0".parse::<StopCondition>().unwrap(),
+            StopCondition::Saturation(0.0)
+        );
+    }
+
+    #[test]
+    fn display_round_trips() {
+        let expression = "authors=5000|nodes=100&saturation=0.05|elapsed=60";
+        let condition: StopCondition = expression.parse().unwrap();
+        assert_eq!(condition.to_string(), expression);
+    }
+
+    #[test]
+    fn combined_conditions() {
+        let condition: StopCondition = "authors=10&floor=100|requests=50".parse().unwrap();
+        let mut state = ScrapeState {
+            authors: 10,
+            oldest: Some(200),
+            ..Default::default()
+        };
+        assert!(!condition.is_met(&state));
+        state.oldest = Some(100);
+        assert!(condition.is_met(&state));
+        state.authors = 0;
+        state.requests = 50;
+        assert!(condition.is_met(&state));
+    }
+
+    #[test]
+    fn targets_take_precedence_over_budgets() {
+        let state = ScrapeState {
+            nodes: 10,
+            requests: 5,
+            ..Default::default()
+        };
+        let target = |expression: &str| {
+            let condition: StopCondition = expression.parse().unwrap();
+            assert!(condition.is_met(&state), "{}", expression);
+            condition.is_target_met(&state)
+        };
+        assert!(target("nodes=10|requests=5"));
+        assert!(target("nodes=10&requests=5"));
+        assert!(target("requests=1|nodes=1&requests=5"));
+        assert!(!target("nodes=100|requests=5"));
+        assert!(!target("requests=5"));
+        assert!(!target("elapsed=0&requests=5"));
+    }
+}
@@ -0,0 +1,188 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+
+use super::nodestructs::Node;
+
+/// How a subreddit's share of the node budget is spread across its time buckets.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq, Hash)]
+pub enum Allocation {
+    /// Every bucket receives the same number of nodes.
+    #[default]
+    Equal,
+    /// Buckets receive nodes in proportion to the number of items the API reports for them.
+    Proportional,
+}
+
+/// A time bucket for a single subreddit along with the number of nodes to sample from it.
+///
+/// Sampled nodes record their bucket. Nodes read from files without one can still be matched
+/// to their stratum with contains().
+#[derive(Clone, Debug, Deserialize, Eq, Hash, PartialEq, Serialize)]
+pub struct Stratum {
+    pub subreddit: String,
+    /// Index of the bucket within the study period; recorded on each Node.
+    pub bucket: u32,
+    /// Inclusive start epoch.
+    pub start: u64,
+    /// Exclusive end epoch.
+    pub end: u64,
+    /// Items in the bucket according to the API's metadata.
+    pub population: Option<u64>,
+    pub target: usize,
+    pub scraped: usize,
+}
+
+impl Stratum {
+    /// Whether `node` was sampled from this stratum.
+    pub fn contains(&self, node: &Node) -> bool {
+        self.subreddit.eq_ignore_ascii_case(&node.subreddit)
+            && (self.start..self.end).contains(&node.created_utc)
+    }
+}
+
+/// Splits `[start, end)` into `buckets` consecutive windows of (nearly) equal length.
+/// Earlier windows are one second longer if the period doesn't divide evenly.
+pub fn split_period(start: u64, end: u64, buckets: u32) -> Vec<(u64, u64)> {
+    let buckets = u64::from(buckets.max(1)).min(end.saturating_sub(start).max(1));
+    let length = end.saturating_sub(start);
+    let (width, extra) = (length / buckets, length % buckets);
+
+    let mut windows = Vec::with_capacity(buckets as usize);
+    let mut window_start = start;
+    for i in 0..buckets {
+        let window_end = window_start + width + u64::from(i < extra);
+        windows.push((window_start, window_end));
+        window_start = window_end;
+    }
+    windows
+}
+
+/// Splits `budget` evenly between subreddits and then across each subreddit's strata according
+/// to `allocation`. Proportional allocation falls back to equal allocation for subreddits
+/// without any reported population. Subreddits are ordered by their first stratum, which
+/// receives any remainder first.
+pub fn allocate(strata: &mut [Stratum], budget: usize, allocation: Allocation) {
+    let mut seen = HashSet::new();
+    let mut subreddits: Vec<&str> = strata
+        .iter()
+        .map(|stratum| stratum.subreddit.as_str())
+        .collect();
+    subreddits.retain(|subreddit| seen.insert(*subreddit));
+    let subreddits: Vec<String> = subreddits.into_iter().map(str::to_owned).collect();
+
+    let sub_budgets = apportion(budget, &vec![1; subreddits.len()]);
+    for (subreddit, sub_budget) in subreddits.iter().zip(sub_budgets) {
+        let mut sub_strata: Vec<&mut Stratum> = strata
+            .iter_mut()
+            .filter(|stratum| stratum.subreddit == *subreddit)
+            .collect();
+
+        let weights: Vec<u64> = match allocation {
+            Allocation::Equal => vec![1; sub_strata.len()],
+            Allocation::Proportional => sub_strata
+                .iter()
+                .map(|stratum| stratum.population.unwrap_or(0))
+                .collect(),
+        };
+
+        for (stratum, target) in sub_strata.iter_mut().zip(apportion(sub_budget, &weights)) {
+            stratum.target = target;
+        }
+    }
+}
+
+/// Splits `budget` in proportion to `weights` using the largest remainder method so that the
+/// shares add up to the budget. Ties go to earlier weights and all zero weights are treated as
+/// equal weights.
+pub fn apportion(budget: usize, weights: &[u64]) -> Vec<usize> {
+    if weights.is_empty() {
+        return Vec::new();
+    }
+
+    let weights = if weights.iter().sum::<u64>() == 0 {
+        vec![1; weights.len()]
+    } else {
+        weights.to_vec()
+    };
+    let total: u128 = weights.iter().map(|weight| u128::from(*weight)).sum();
+
+    let mut shares = Vec::with_capacity(weights.len());
+    let mut remainders = Vec::with_capacity(weights.len());
+    for (i, weight) in weights.iter().enumerate() {
+        let share = budget as u128 * u128::from(*weight);
+        shares.push((share / total) as usize);
+        remainders.push((share % total, i));
+    }
+
+    let assigned: usize = shares.iter().sum();
+    remainders.sort_by(|a, b| b.0.cmp(&a.0).then(a.1.cmp(&b.1)));
+    for (_, i) in remainders.into_iter().take(budget - assigned) {
+        shares[i] += 1;
+    }
+    shares
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn stratum(subreddit: &str, bucket: u32, population: Option<u64>) -> Stratum {
+        Stratum {
+            subreddit: subreddit.to_string(),
+            bucket,
+            start: 0,
+            end: 0,
+            population,
+            target: 0,
+            scraped: 0,
+        }
+    }
+
+    #[test]
+    fn split_period_covers_window() {
+        assert_eq!(split_period(0, 10, 3), vec![(0, 4), (4, 7), (7, 10)]);
+        assert_eq!(split_period(5, 7, 10), vec![(5, 6), (6, 7)]);
+    }
+
+    #[test]
+    fn allocate_equal_and_proportional() {
+        let mut strata = vec![
+            stratum("gaming", 0, Some(300)),
+            stratum("gaming", 1, Some(100)),
+            stratum("games", 0, None),
+            stratum("games", 1, None),
+        ];
+
+        allocate(&mut strata, 11, Allocation::Equal);
+        let targets: Vec<_> = strata.iter().map(|stratum| stratum.target).collect();
+        assert_eq!(targets, vec![3, 3, 3, 2]);
+
+        allocate(&mut strata, 8, Allocation::Proportional);
+        let targets: Vec<_> = strata.iter().map(|stratum| stratum.target).collect();
+        assert_eq!(targets, vec![3, 1, 2, 2]);
+
+        // Interleaved subreddits still split the budget between two subreddits, not four.
+        strata.swap(1, 2);
+        allocate(&mut strata, 8, Allocation::Equal);
+        let targets: Vec<_> = strata.iter().map(|stratum| stratum.target).collect();
+        assert_eq!(targets, vec![2, 2, 2, 2]);
+    }
+
+    #[test]
+    fn contains_nodes_in_its_window() {
+        let mut gaming = stratum("gaming", 1, None);
+        gaming.start = 100;
+        gaming.end = 200;
+        let node = |created_utc, subreddit: &str| Node {
+            author: "link".to_string(),
+            created_utc,
+            permalink: String::new(),
+            subreddit: subreddit.to_string(),
+            stratum: None,
+        };
+        assert!(gaming.contains(&node(100, "Gaming")));
+        assert!(gaming.contains(&node(199, "gaming")));
+        assert!(!gaming.contains(&node(200, "gaming")));
+        assert!(!gaming.contains(&node(150, "games")));
+    }
+}
@@ -0,0 +1,49 @@
+use std::{
+    fs,
+    ops::Deref,
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+// Tells apart the directories of tests running in the same process.
+static NEXT: AtomicUsize = AtomicUsize::new(0);
+
+/// Empty directory for a test's files that is removed when dropped, even if the test panics.
+///
+/// Directories are named after the test, the process, and a counter so that tests running in
+/// parallel, including other test binaries, never share one.
+pub struct TestDir(PathBuf);
+
+impl TestDir {
+    pub fn new(name: &str) -> Self {
+        let path = std::env::temp_dir().join(format!(
+            "{}-{}-{}-{}",
+            env!("CARGO_PKG_NAME"),
+            name,
+            std::process::id(),
+            NEXT.fetch_add(1, Ordering::Relaxed)
+        ));
+        // Left behind by an earlier process with the same id if it was killed.
+        let _ = fs::remove_dir_all(&path);
+        fs::create_dir_all(&path).unwrap();
+        TestDir(path)
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.0
+    }
+}
+
+impl Deref for TestDir {
+    type Target = Path;
+
+    fn deref(&self) -> &Path {
+        &self.0
+    }
+}
+
+impl Drop for TestDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}